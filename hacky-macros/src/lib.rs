@@ -0,0 +1,285 @@
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+use hacky::diagnostic::Diagnostic;
+use hacky::{AsmError, Assembler};
+
+/// Assembles hack source at compile time into a `[u16; N]`.
+///
+/// The source can be written directly, one instruction per line, or as a string literal for
+/// anything that isn't valid rust tokens. Errors are reported at the offending token.
+///
+/// ```
+/// use hacky_macros::hack_asm;
+///
+/// const ADD: [u16; 6] = hack_asm! {
+///     @2
+///     D=A
+///     @3
+///     D=D+A
+///     @0
+///     M=D
+/// };
+///
+/// assert_eq!(ADD[1], 0b1110110000010000);
+/// assert_eq!(hack_asm!("@5\nD=A // five"), [5, 0xEC10]);
+/// ```
+///
+/// ```compile_fail
+/// use hacky_macros::hack_asm;
+///
+/// let program = hack_asm! {
+///     D=M*2
+/// };
+/// ```
+#[proc_macro]
+pub fn hack_asm(input: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+
+    //a lone string literal is the source itself, otherwise the tokens are laid out as they were written
+    let (source, pieces) = match tokens.as_slice() {
+        [TokenTree::Literal(lit)] if lit.to_string().starts_with(['"', 'r']) => {
+            match string_value(&lit.to_string()) {
+                Some(s) => (s, Vec::new()),
+                None => {
+                    return errors_block(error(lit.span(), "expected a string of hack source"));
+                }
+            }
+        }
+
+        _ => {
+            let mut pieces = Vec::new();
+            flatten(tokens, &mut pieces);
+            let positions: Vec<(&str, usize, usize)> = pieces
+                .iter()
+                .map(|p| (p.text.as_str(), p.line, p.col))
+                .collect();
+            (layout(&positions), pieces)
+        }
+    };
+
+    let assembler = Assembler::new(source.as_bytes());
+    match assembler.assemble_to_words() {
+        Ok(words) => array(&words),
+        Err(errors) => errors_block(
+            errors
+                .iter()
+                .map(|e| report(e, &assembler, &pieces))
+                .collect(),
+        ),
+    }
+}
+
+//a token of the input and where it was written, lines and columns are 1-based
+struct Piece {
+    text: String,
+    line: usize,
+    col: usize,
+    span: Span,
+}
+
+impl Piece {
+    fn new(text: String, span: Span) -> Self {
+        Piece {
+            text,
+            line: span.line(),
+            col: span.column(),
+            span,
+        }
+    }
+}
+
+//labels come in as parenthesised groups, so they are split back into ( name )
+fn flatten(tokens: impl IntoIterator<Item = TokenTree>, out: &mut Vec<Piece>) {
+    for t in tokens {
+        match t {
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::None => ("", ""),
+                };
+
+                out.push(Piece::new(open.into(), g.span_open()));
+                flatten(g.stream(), out);
+                out.push(Piece::new(close.into(), g.span_close()));
+            }
+
+            t => out.push(Piece::new(t.to_string(), t.span())),
+        }
+    }
+}
+
+//rebuilds the source text with every token on its original line and column, relative to the
+//first line, so the hacky lexer sees the same lines the user wrote
+fn layout(positions: &[(&str, usize, usize)]) -> String {
+    let mut out = String::new();
+    let mut line = positions.first().map_or(1, |p| p.1);
+    let mut col = 1;
+
+    for &(text, token_line, token_col) in positions {
+        while line < token_line {
+            out.push('\n');
+            line += 1;
+            col = 1;
+        }
+
+        //tokens from other macros can have positions out of order, a space keeps them apart
+        if token_col > col {
+            out.push_str(&" ".repeat(token_col - col));
+            col = token_col;
+        } else if col > 1 && token_col < col {
+            out.push(' ');
+            col += 1;
+        }
+
+        out.push_str(text);
+        col += text.chars().count();
+    }
+
+    out
+}
+
+//the token an error is about, the first one that overlaps its span
+//an error in an included file is put on the .include
+fn find_span(pieces: &[Piece], assembler: &Assembler, e: &AsmError) -> Span {
+    let s = assembler.sources().in_main(e.span());
+    let first_line = pieces.first().map_or(1, |p| p.line);
+
+    let start = s.col as usize;
+    let end = start + (s.len as usize).max(1);
+
+    pieces
+        .iter()
+        .find(|p| {
+            let line = p.line - first_line + 1;
+            line == s.line as usize && p.col < end && p.col + p.text.chars().count() > start
+        })
+        .map_or(Span::call_site(), |p| p.span)
+}
+
+fn report(e: &AsmError, assembler: &Assembler, pieces: &[Piece]) -> TokenStream {
+    let d = Diagnostic::from(e);
+
+    let mut message = d.message;
+    if let Some(help) = d.help {
+        message = format!("{message}, {help}");
+    }
+
+    error(find_span(pieces, assembler, e), &message)
+}
+
+//compile_error!("message"); with every token at span
+fn error(span: Span, message: &str) -> TokenStream {
+    let mut lit = Literal::string(message);
+    lit.set_span(span);
+
+    let mut args = Group::new(Delimiter::Parenthesis, TokenTree::Literal(lit).into());
+    args.set_span(span);
+
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+
+    let mut semi = Punct::new(';', Spacing::Alone);
+    semi.set_span(span);
+
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(args),
+        TokenTree::Punct(semi),
+    ]
+    .into_iter()
+    .collect()
+}
+
+//every error in one block so they are all reported, it ends in a loop so the block can stand in
+//for an array of any length without causing a type error as well
+fn errors_block(errors: TokenStream) -> TokenStream {
+    let mut inner = errors;
+    inner.extend("loop {}".parse::<TokenStream>().unwrap());
+
+    TokenTree::Group(Group::new(Delimiter::Brace, inner)).into()
+}
+
+fn array(words: &[u16]) -> TokenStream {
+    if words.is_empty() {
+        return "[0u16; 0]".parse().unwrap();
+    }
+
+    let words: Vec<String> = words.iter().map(|w| format!("{w:#06x}u16")).collect();
+    format!("[{}]", words.join(", ")).parse().unwrap()
+}
+
+//the contents of a plain or raw string literal, escapes in plain strings are handled for the
+//few that make sense in hack source
+fn string_value(lit: &str) -> Option<String> {
+    if let Some(raw) = lit.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let inner = &raw[hashes..raw.len() - hashes];
+        return inner.strip_prefix('"')?.strip_suffix('"').map(String::from);
+    }
+
+    let inner = lit.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            '\\' => out.push('\\'),
+            '"' => out.push('"'),
+            '\'' => out.push('\''),
+            '0' => out.push('\0'),
+            //a line continuation skips the newline and any indent after it
+            '\n' => {
+                let rest = chars.as_str().trim_start();
+                chars = rest.chars();
+            }
+            _ => return None,
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout_keeps_lines_and_columns() {
+        let positions = [
+            ("(", 10, 9),
+            ("LOOP", 10, 10),
+            (")", 10, 14),
+            ("@", 11, 9),
+            ("LOOP", 11, 10),
+            ("0", 13, 9),
+            (";", 13, 10),
+            ("JMP", 13, 11),
+        ];
+
+        assert_eq!(
+            layout(&positions),
+            "        (LOOP)\n        @LOOP\n\n        0;JMP"
+        );
+    }
+
+    #[test]
+    fn string_values() {
+        assert_eq!(string_value(r#""@2\nD=A""#).as_deref(), Some("@2\nD=A"));
+        assert_eq!(
+            string_value(r###"r#"@2 "x""#"###).as_deref(),
+            Some("@2 \"x\"")
+        );
+        assert_eq!(string_value(r#""@2\q""#), None);
+    }
+}
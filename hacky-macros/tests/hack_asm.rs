@@ -0,0 +1,72 @@
+use hacky::Assembler;
+use hacky::emulator::{Machine, Stop};
+use hacky_macros::hack_asm;
+
+fn assemble(src: &str) -> Vec<u16> {
+    Assembler::new(src.as_bytes()).assemble_to_words().unwrap()
+}
+
+#[test]
+fn matches_the_assembler() {
+    let program = hack_asm! {
+        // sum = 1 + ... + 10
+        @i
+        M=1
+        @sum
+        M=0
+    (LOOP)
+        @i
+        D=M
+        @10
+        D=D-A
+        @END
+        D;JGT
+        @i
+        D=M
+        @sum
+        M=D+M
+        @i
+        M=M+1
+        @LOOP
+        0;JMP
+    (END)
+        @END
+        0;JMP
+    };
+
+    let expected = assemble(
+        "@i\nM=1\n@sum\nM=0\n(LOOP)\n@i\nD=M\n@10\nD=D-A\n@END\nD;JGT\n@i\nD=M\n@sum\nM=D+M\n\
+         @i\nM=M+1\n@LOOP\n0;JMP\n(END)\n@END\n0;JMP\n",
+    );
+    assert_eq!(program.to_vec(), expected);
+
+    let mut machine = Machine::new();
+    machine.load(&program).unwrap();
+    assert_eq!(machine.run(1000).unwrap(), Stop::Halted);
+    assert_eq!(machine.ram()[17], 55);
+}
+
+#[test]
+fn string_source() {
+    const PROGRAM: [u16; 3] = hack_asm!("@R0 // first register\nAM=M-1\nD=!A");
+
+    assert_eq!(PROGRAM.to_vec(), assemble("@R0\nAM=M-1\nD=!A\n"));
+    assert_eq!(hack_asm!(r"@SCREEN"), [16384]);
+}
+
+#[test]
+fn symbols_with_dots() {
+    let program = hack_asm! {
+    (Main.main$loop)
+        @Main.main$loop
+        D;JNE
+    };
+
+    assert_eq!(program, [0, 0b1110001100000101]);
+}
+
+#[test]
+fn empty() {
+    let program: [u16; 0] = hack_asm! {};
+    assert!(program.is_empty());
+}
@@ -0,0 +1,179 @@
+use crate::instruction::{Comp, Dest, Jump};
+use crate::parser::{Command, Statement, Symbol};
+use crate::span::Span;
+use crate::{AsmError, Assembler, Options};
+
+//builds a program in code instead of writing source, the statements are the same ones the parser
+//makes so they go through the same symbol table and encoding
+//
+//  let mut b = ProgramBuilder::new();
+//  let end = b.fresh_label("end");
+//  b.a_const(2)
+//      .c(Dest::D, Comp::A, Jump::Null)
+//      .label(&end)
+//      .a_symbol(&end)
+//      .c(Dest::Null, Comp::Zero, Jump::JMP);
+//  let words = b.assemble()?;
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+    statements: Vec<Statement>,
+    next_label: usize,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //each statement is given the span it has in to_source, one per line
+    fn push(&mut self, command: Command) -> &mut Self {
+        let line = self.statements.len() as u32 + 1;
+        let text_len = command.to_string().chars().count() as u32;
+
+        let name_span = match &command {
+            Command::ACommand { .. } => Span::new(line, 2, text_len - 1),
+            Command::LCommand { .. } => Span::new(line, 2, text_len - 2),
+            Command::CCommand { .. } => Span::new(line, 1, text_len),
+            Command::Constant { name, .. } => Span::new(line, 6, name.chars().count() as u32),
+            Command::Alias { name, .. } => Span::new(line, 8, name.chars().count() as u32),
+            Command::Var { name, .. } => Span::new(line, 6, name.chars().count() as u32),
+        };
+
+        self.statements.push(Statement {
+            command,
+            span: Span::new(line, 1, text_len),
+            name_span,
+        });
+        self
+    }
+
+    //@value, values above 32767 are reported when assembling
+    pub fn a_const(&mut self, value: u16) -> &mut Self {
+        self.push(Command::ACommand {
+            symbol: Symbol::Decimal(value),
+        })
+    }
+
+    //@name, a label, predefined symbol or variable
+    pub fn a_symbol(&mut self, name: impl Into<String>) -> &mut Self {
+        self.push(Command::ACommand {
+            symbol: Symbol::Name(name.into()),
+        })
+    }
+
+    //dest=comp;jump, with Dest::Null and Jump::Null for the parts that are left out
+    pub fn c(&mut self, dest: Dest, comp: Comp, jump: Jump) -> &mut Self {
+        self.push(Command::CCommand {
+            dest: (dest != Dest::Null).then_some(dest),
+            comp,
+            jmp: (jump != Jump::Null).then_some(jump),
+        })
+    }
+
+    //(name)
+    pub fn label(&mut self, name: impl Into<String>) -> &mut Self {
+        self.push(Command::LCommand { label: name.into() })
+    }
+
+    //a new label name each call, e.g. loop$0 then loop$1
+    //$ is used as hand written hack rarely has it, so the names won't clash with the user's
+    pub fn fresh_label(&mut self, prefix: &str) -> String {
+        let name = format!("{prefix}${}", self.next_label);
+        self.next_label += 1;
+        name
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    //the program as hack source, one command per line
+    pub fn to_source(&self) -> String {
+        self.statements
+            .iter()
+            .map(|s| format!("{}\n", s.command))
+            .collect()
+    }
+
+    pub fn into_assembler(self, options: Options) -> Assembler {
+        Assembler::from_statements(self.statements, options)
+    }
+
+    pub fn assemble(&self) -> Result<Vec<u16>, Vec<AsmError>> {
+        self.clone()
+            .into_assembler(Options::default())
+            .assemble_to_words()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{NumberUse, ParseError};
+    use crate::symbol::SymbolError;
+
+    fn count_down() -> ProgramBuilder {
+        let mut b = ProgramBuilder::new();
+        let top = b.fresh_label("loop");
+        let end = b.fresh_label("end");
+
+        b.a_const(10)
+            .c(Dest::D, Comp::A, Jump::Null)
+            .label(&top)
+            .a_symbol(&end)
+            .c(Dest::Null, Comp::D, Jump::JEQ)
+            .a_symbol("count")
+            .c(Dest::DM, Comp::DMinusOne, Jump::Null)
+            .a_symbol(&top)
+            .c(Dest::Null, Comp::Zero, Jump::JMP)
+            .label(&end)
+            .a_symbol(&end)
+            .c(Dest::Null, Comp::Zero, Jump::JMP);
+        b
+    }
+
+    #[test]
+    fn source_and_words_agree() {
+        let b = count_down();
+        let source = b.to_source();
+
+        assert!(source.starts_with("@10\nD=A\n(loop$0)\n@end$1\nD;JEQ\n@count\nDM=D-1\n"));
+        assert_eq!(
+            b.assemble().unwrap(),
+            Assembler::new(source.as_bytes())
+                .assemble_to_words()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn spans_point_into_source() {
+        let b = count_down();
+
+        assert_eq!(b.statements()[2].name_span, Span::new(3, 2, 6));
+        assert_eq!(b.statements()[3].name_span, Span::new(4, 2, 5));
+        assert_eq!(b.statements()[6].span, Span::new(7, 1, 6));
+    }
+
+    #[test]
+    fn invalid_values_are_reported() {
+        let mut b = ProgramBuilder::new();
+        b.a_const(40000).a_symbol("1st").label("has space");
+
+        let errors = b.assemble().unwrap_err();
+
+        match errors.as_slice() {
+            [
+                AsmError::Parse(ParseError::IntegerOverflow(i, NumberUse::AInstruction, a)),
+                AsmError::Symbol(SymbolError::InvalidSymbol(n, _)),
+                AsmError::Symbol(SymbolError::InvalidSymbol(l, _)),
+            ] => {
+                assert_eq!(i, "40000");
+                assert_eq!(*a, Span::new(1, 2, 5));
+                assert_eq!(n, "1st");
+                assert_eq!(l, "has space");
+            }
+            r => panic!("expected invalid values, got {r:?}"),
+        }
+    }
+}
@@ -54,66 +54,28 @@ impl Code {
         "111",
     ];
 
+    //mnemonics are kept in the same order as the bit maps above, so a mnemonic's
+    //position gives its encoding and an encoding's position gives its mnemonic
+    const CMP_NAMES: [&'static str; 28] = [
+        "0", "1", "-1", "D", "A", "!D", "!A", "-D", "-A", "D+1", "A+1", "D-1", "A-1", "D+A",
+        "D-A", "A-D", "D&A", "D|A", "M", "!M", "-M", "M+1", "M-1", "D+M", "D-M", "M-D", "D&M",
+        "D|M",
+    ];
+
+    const DST_NAMES: [&'static str; 8] = ["null", "M", "D", "DM", "A", "AM", "AD", "ADM"];
+
+    const JMP_NAMES: [&'static str; 8] = ["null", "JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+
     fn cmp_index(cmd: &str) -> Option<usize> {
-        match cmd {
-            "0" => Some(0),
-            "1" => Some(1),
-            "-1" => Some(2),
-            "D" => Some(3),
-            "A" => Some(4),
-            "!D" => Some(5),
-            "!A" => Some(6),
-            "-D" => Some(7),
-            "-A" => Some(8),
-            "D+1" => Some(9),
-            "A+1" => Some(10),
-            "D-1" => Some(11),
-            "A-1" => Some(12),
-            "D+A" => Some(13),
-            "D-A" => Some(14),
-            "A-D" => Some(15),
-            "D&A" => Some(16),
-            "D|A" => Some(17),
-            "M" => Some(18),
-            "!M" => Some(19),
-            "-M" => Some(20),
-            "M+1" => Some(21),
-            "M-1" => Some(22),
-            "D+M" => Some(23),
-            "D-M" => Some(24),
-            "M-D" => Some(25),
-            "D&M" => Some(26),
-            "D|M" => Some(27),
-            _ => None,
-        }
+        Self::CMP_NAMES.iter().position(|&n| n == cmd)
     }
 
     fn dst_index(cmd: &str) -> Option<usize> {
-        match cmd {
-            "null" => Some(0),
-            "M" => Some(1),
-            "D" => Some(2),
-            "DM" => Some(3),
-            "A" => Some(4),
-            "AM" => Some(5),
-            "AD" => Some(6),
-            "ADM" => Some(7),
-            _ => None,
-        }
+        Self::DST_NAMES.iter().position(|&n| n == cmd)
     }
 
     fn jmp_index(cmd: &str) -> Option<usize> {
-        match cmd {
-            "null" => Some(0),
-            "JGT" => Some(1),
-            "JEQ" => Some(2),
-            "JGE" => Some(3),
-            "JLT" => Some(4),
-            "JNE" => Some(5),
-            "JLE" => Some(6),
-            "JMP" => Some(7),
-            _ => None,
-        }
+        Self::JMP_NAMES.iter().position(|&n| n == cmd)
     }
 
     pub fn comp(cmd: &str) -> Option<&'static str> {
@@ -127,6 +89,41 @@ impl Code {
     pub fn jump(cmd: &str) -> Option<&'static str> {
         Self::jmp_index(cmd).map(|i| Self::JMP_MAP[i])
     }
+
+    //every mnemonic, in the same order as the bit maps
+    pub fn comp_names() -> &'static [&'static str] {
+        &Self::CMP_NAMES
+    }
+
+    pub fn dest_names() -> &'static [&'static str] {
+        &Self::DST_NAMES
+    }
+
+    pub fn jump_names() -> &'static [&'static str] {
+        &Self::JMP_NAMES
+    }
+
+    //inverse lookups, take the bits of a field and give back its mnemonic
+    pub fn comp_name(bits: &str) -> Option<&'static str> {
+        Self::CMP_MAP
+            .iter()
+            .position(|&b| b == bits)
+            .map(|i| Self::CMP_NAMES[i])
+    }
+
+    pub fn dest_name(bits: &str) -> Option<&'static str> {
+        Self::DST_MAP
+            .iter()
+            .position(|&b| b == bits)
+            .map(|i| Self::DST_NAMES[i])
+    }
+
+    pub fn jump_name(bits: &str) -> Option<&'static str> {
+        Self::JMP_MAP
+            .iter()
+            .position(|&b| b == bits)
+            .map(|i| Self::JMP_NAMES[i])
+    }
 }
 
 #[cfg(test)]
@@ -153,4 +150,13 @@ mod tests {
         assert_eq!(Code::jump("JMP"), Some("111"));
         assert_eq!(Code::jump("INVALID"), None);
     }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(Code::comp_name("0000010"), Some("D+A"));
+        assert_eq!(Code::comp_name("1110000"), Some("M"));
+        assert_eq!(Code::comp_name("1111111"), None);
+        assert_eq!(Code::dest_name("011"), Some("DM"));
+        assert_eq!(Code::jump_name("111"), Some("JMP"));
+    }
 }
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::symbol::{SymbolKind, SymbolTable};
+
+//words per line of the generated arrays
+const LINE_LEN: usize = 8;
+
+//labels and variables as constant names, predefined symbols are left out as every hack program
+//has them. hack names can hold . $ and : so those become _, and any clash gets a number on the end
+fn constants(symbols: &SymbolTable, reserved: &[&str]) -> Vec<(String, u16, SymbolKind)> {
+    let mut taken: HashSet<String> = reserved.iter().map(|s| s.to_string()).collect();
+    let mut out = Vec::new();
+
+    for e in symbols.iter().filter(|e| e.kind != SymbolKind::Predefined) {
+        let base: String = e
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        let mut name = base.clone();
+        let mut n = 2;
+        while taken.contains(&name) {
+            name = format!("{base}_{n}");
+            n += 1;
+        }
+
+        taken.insert(name.clone());
+        out.push((name, e.address, e.kind));
+    }
+
+    out
+}
+
+fn write_array<W: Write>(w: &mut W, words: &[u16]) -> io::Result<()> {
+    for line in words.chunks(LINE_LEN) {
+        let line: Vec<String> = line.iter().map(|word| format!("0x{word:04X},")).collect();
+        writeln!(w, "    {}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+//a rust module with the program as a static array and a const for each symbol
+pub fn write_rust<W: Write>(w: &mut W, words: &[u16], symbols: &SymbolTable) -> io::Result<()> {
+    writeln!(w, "// generated by hacky, do not edit")?;
+    writeln!(w)?;
+    writeln!(w, "pub static PROGRAM: [u16; {}] = [", words.len())?;
+    write_array(w, words)?;
+    writeln!(w, "];")?;
+
+    let constants = constants(symbols, &["PROGRAM"]);
+    if !constants.is_empty() {
+        writeln!(w)?;
+    }
+
+    for (name, address, kind) in constants {
+        writeln!(w, "pub const {name}: u16 = {address}; // {}", kind.name())?;
+    }
+
+    Ok(())
+}
+
+//a c header with the program as a const array, symbols are prefixed with HACK_ as they are macros
+pub fn write_c<W: Write>(w: &mut W, words: &[u16], symbols: &SymbolTable) -> io::Result<()> {
+    writeln!(w, "/* generated by hacky, do not edit */")?;
+    writeln!(w, "#ifndef HACK_PROGRAM_H")?;
+    writeln!(w, "#define HACK_PROGRAM_H")?;
+    writeln!(w)?;
+    writeln!(w, "#include <stdint.h>")?;
+    writeln!(w)?;
+    writeln!(w, "#define HACK_PROGRAM_LEN {}", words.len())?;
+    writeln!(w)?;
+    //iso c has no zero length arrays, so an empty program still gets a word nothing should read
+    if words.is_empty() {
+        writeln!(w, "static const uint16_t hack_program[1] = {{0}};")?;
+    } else {
+        writeln!(
+            w,
+            "static const uint16_t hack_program[HACK_PROGRAM_LEN] = {{"
+        )?;
+        write_array(w, words)?;
+        writeln!(w, "}};")?;
+    }
+
+    let constants = constants(symbols, &["PROGRAM_H", "PROGRAM_LEN"]);
+    if !constants.is_empty() {
+        writeln!(w)?;
+    }
+
+    for (name, address, kind) in constants {
+        writeln!(w, "#define HACK_{name} {address} /* {} */", kind.name())?;
+    }
+
+    writeln!(w)?;
+    writeln!(w, "#endif")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::span::Span;
+
+    fn table() -> SymbolTable {
+        let mut table = SymbolTable::with_predefined(&[("SCREEN".into(), 16384)]);
+        let span = Some(Span::new(1, 1, 1));
+
+        table.insert("math.loop$1".into(), 2, SymbolKind::Label, span);
+        table.insert("MATH_LOOP_1".into(), 5, SymbolKind::Label, span);
+        table.insert("program".into(), 16, SymbolKind::Variable, span);
+        table
+    }
+
+    #[test]
+    fn rust_module() {
+        let mut out = Vec::new();
+        write_rust(&mut out, &[2, 0xEC10], &table()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "// generated by hacky, do not edit\n\
+             \n\
+             pub static PROGRAM: [u16; 2] = [\n\
+             \x20   0x0002, 0xEC10,\n\
+             ];\n\
+             \n\
+             pub const MATH_LOOP_1: u16 = 2; // label\n\
+             pub const MATH_LOOP_1_2: u16 = 5; // label\n\
+             pub const PROGRAM_2: u16 = 16; // variable\n"
+        );
+    }
+
+    #[test]
+    fn c_header() {
+        let words: Vec<u16> = (0..9).collect();
+        let mut out = Vec::new();
+        write_c(&mut out, &words, &SymbolTable::new()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "/* generated by hacky, do not edit */\n\
+             #ifndef HACK_PROGRAM_H\n\
+             #define HACK_PROGRAM_H\n\
+             \n\
+             #include <stdint.h>\n\
+             \n\
+             #define HACK_PROGRAM_LEN 9\n\
+             \n\
+             static const uint16_t hack_program[HACK_PROGRAM_LEN] = {\n\
+             \x20   0x0000, 0x0001, 0x0002, 0x0003, 0x0004, 0x0005, 0x0006, 0x0007,\n\
+             \x20   0x0008,\n\
+             };\n\
+             \n\
+             #endif\n"
+        );
+
+        let mut out = Vec::new();
+        write_c(&mut out, &[0], &table()).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("#define HACK_MATH_LOOP_1 2 /* label */\n")
+        );
+    }
+
+    #[test]
+    fn empty_c_header() {
+        let mut out = Vec::new();
+        write_c(&mut out, &[], &SymbolTable::new()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains(
+            "#define HACK_PROGRAM_LEN 0\n\
+             \n\
+             static const uint16_t hack_program[1] = {0};\n\
+             \n\
+             #endif\n"
+        ));
+    }
+}
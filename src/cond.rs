@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use crate::lexer::{self, Token, TokenKind};
+use crate::parser::ParseError;
+use crate::span::Span;
+
+//an open .if, .ifdef or .ifndef
+struct Branch {
+    //the span of the directive that opened it, for an .if that is never closed
+    span: Span,
+    //whether the lines in the current branch are kept
+    active: bool,
+    //whether the .if or .else part was the one taken, so .else can flip it
+    taken: bool,
+    //whether the whole .if is inside a branch that isn't taken
+    skipped: bool,
+    seen_else: bool,
+}
+
+//decides which lines are assembled, going through them in order
+//
+//  .if COND / .ifdef NAME / .ifndef NAME
+//      ...
+//  .else
+//      ...
+//  .endif
+//
+//names are the predefined symbols (which include -D definitions) and every .equ or .define
+//kept so far, so conditions are known before any labels are
+//
+//that is also before macros are expanded, so a condition in a macro body would be decided once
+//where the macro is defined rather than at each call, and is rejected instead
+pub struct Conditions {
+    values: HashMap<String, u16>,
+    open: Vec<Branch>,
+    //the span of the .macro whose body is being read
+    in_macro: Option<Span>,
+}
+
+impl Conditions {
+    pub fn new(predefined: &[(String, u16)]) -> Self {
+        Conditions {
+            values: predefined.iter().cloned().collect(),
+            open: Vec::new(),
+            in_macro: None,
+        }
+    }
+
+    fn active(&self) -> bool {
+        self.open.last().is_none_or(|b| b.active)
+    }
+
+    //whether a line should be assembled, the conditional directives themselves never are
+    pub fn keep(&mut self, line: &[Token], errors: &mut Vec<ParseError>) -> bool {
+        let first = &line[0];
+        let directive = if first.kind == TokenKind::Word {
+            first.text.as_str()
+        } else {
+            ""
+        };
+        let span = lexer::span_of(line).unwrap();
+
+        match directive {
+            ".if" | ".ifdef" | ".ifndef" | ".else" | ".endif"
+                if self.in_macro.is_some() && self.active() =>
+            {
+                errors.push(ParseError::ConditionInMacro(span, self.in_macro.unwrap()));
+                false
+            }
+
+            ".if" | ".ifdef" | ".ifndef" => {
+                let skipped = !self.active();
+
+                //nothing in a skipped branch is looked at, so it can use names that don't exist
+                let taken = !skipped
+                    && match self.condition(directive, &line[1..], span) {
+                        Ok(t) => t,
+                        Err(e) => {
+                            errors.push(e);
+                            false
+                        }
+                    };
+
+                self.open.push(Branch {
+                    span,
+                    active: taken,
+                    taken,
+                    skipped,
+                    seen_else: false,
+                });
+                false
+            }
+
+            ".else" => {
+                match self.open.last_mut() {
+                    Some(b) if !b.seen_else && line.len() == 1 => {
+                        b.seen_else = true;
+                        b.active = !b.skipped && !b.taken;
+                    }
+                    _ => errors.push(ParseError::InvalidDirective(first.text.clone(), span)),
+                }
+                false
+            }
+
+            ".endif" => {
+                if line.len() != 1 || self.open.pop().is_none() {
+                    errors.push(ParseError::InvalidDirective(first.text.clone(), span));
+                }
+                false
+            }
+
+            _ if !self.active() => false,
+
+            ".macro" => {
+                self.in_macro = Some(span);
+                true
+            }
+
+            ".endm" => {
+                self.in_macro = None;
+                true
+            }
+
+            ".equ" | ".define" => {
+                //a bad definition is reported by the parser, here it's just not usable
+                if let [_, name, value] = line
+                    && let Some(v) = lexer::number_value(value).filter(|v| *v <= 32767)
+                {
+                    self.values.insert(name.text.clone(), v as u16);
+                }
+                true
+            }
+
+            _ => true,
+        }
+    }
+
+    //errors for every .if still open at the end of the source
+    pub fn finish(self) -> Vec<ParseError> {
+        self.open
+            .into_iter()
+            .map(|b| ParseError::UnterminatedIf(b.span))
+            .collect()
+    }
+
+    fn condition(&self, directive: &str, tokens: &[Token], span: Span) -> Result<bool, ParseError> {
+        match (directive, tokens) {
+            (".ifdef", [name]) if name.kind == TokenKind::Word => {
+                Ok(self.values.contains_key(&name.text))
+            }
+
+            (".ifndef", [name]) if name.kind == TokenKind::Word => {
+                Ok(!self.values.contains_key(&name.text))
+            }
+
+            (".if", [value]) => Ok(self.value(value)? != 0),
+
+            //comparisons are made of two punctuation tokens, e.g. = and = for ==
+            (".if", [left, op @ .., right]) => {
+                let (l, r) = (self.value(left)?, self.value(right)?);
+
+                match lexer::text_of(op).as_str() {
+                    "==" => Ok(l == r),
+                    "!=" => Ok(l != r),
+                    "<" => Ok(l < r),
+                    "<=" => Ok(l <= r),
+                    ">" => Ok(l > r),
+                    ">=" => Ok(l >= r),
+                    _ => Err(ParseError::InvalidCondition(span)),
+                }
+            }
+
+            _ => Err(ParseError::InvalidCondition(span)),
+        }
+    }
+
+    fn value(&self, token: &Token) -> Result<u32, ParseError> {
+        if lexer::is_number(token) {
+            return lexer::number_value(token)
+                .ok_or_else(|| ParseError::InvalidNumber(token.text.clone(), token.span));
+        }
+
+        if token.kind != TokenKind::Word {
+            return Err(ParseError::InvalidCondition(token.span));
+        }
+
+        self.values
+            .get(&token.text)
+            .map(|v| u32::from(*v))
+            .ok_or_else(|| ParseError::UndefinedCondition(token.text.clone(), token.span))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kept(src: &str, predefined: &[(String, u16)]) -> (Vec<String>, Vec<ParseError>) {
+        let mut c = Conditions::new(predefined);
+        let mut errors = Vec::new();
+
+        let lines = lexer::tokenize(src)
+            .lines
+            .into_iter()
+            .filter(|l| c.keep(l, &mut errors))
+            .map(|l| lexer::text_of(&l))
+            .collect();
+        errors.extend(c.finish());
+
+        (lines, errors)
+    }
+
+    #[test]
+    fn branches() {
+        let src = ".equ LEVEL 2\n\
+                   .ifdef DEBUG\n@1\n.else\n@2\n.endif\n\
+                   .if LEVEL >= 2\n@3\n.if LEVEL == 3\n@4\n.endif\n.endif\n\
+                   .ifndef LEVEL\n@5\n.endif\n";
+
+        let (lines, errors) = kept(src, &[]);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(lines, vec![".equLEVEL2", "@2", "@3"]);
+
+        let (lines, _) = kept(src, &[("DEBUG".into(), 1)]);
+        assert_eq!(lines, vec![".equLEVEL2", "@1", "@3"]);
+    }
+
+    #[test]
+    fn skipped_branches_are_not_evaluated() {
+        let src = ".if 0\n.if NOWHERE\n@1\n.else\n@2\n.endif\n.else\n@3\n.endif\n";
+        let (lines, errors) = kept(src, &[]);
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(lines, vec!["@3"]);
+    }
+
+    #[test]
+    fn errors() {
+        let src = ".if NOWHERE\n.endif\n.if 1 = 2\n.endif\n.else\n.ifdef X\n.else\n.else\n";
+        let (_, errors) = kept(src, &[]);
+
+        match errors.as_slice() {
+            [
+                ParseError::UndefinedCondition(name, _),
+                ParseError::InvalidCondition(_),
+                ParseError::InvalidDirective(stray, _),
+                ParseError::InvalidDirective(second, _),
+                ParseError::UnterminatedIf(span),
+            ] => {
+                assert_eq!(name, "NOWHERE");
+                assert_eq!(stray, ".else");
+                assert_eq!(second, ".else");
+                assert_eq!(*span, Span::new(6, 1, 8));
+            }
+            r => panic!("expected conditional errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn conditions_in_macros() {
+        let src = ".ifdef DEBUG\n.macro LOG\n@1\n.endm\n.endif\n\
+                   .macro TWICE\n.ifdef DEBUG\n@2\n.endif\n.endm\n";
+        let (lines, errors) = kept(src, &[("DEBUG".into(), 1)]);
+
+        //a whole macro can be conditional, but not part of its body
+        assert_eq!(
+            lines,
+            vec![".macroLOG", "@1", ".endm", ".macroTWICE", "@2", ".endm"]
+        );
+        match errors.as_slice() {
+            [
+                ParseError::ConditionInMacro(ifdef, m),
+                ParseError::ConditionInMacro(endif, _),
+            ] => {
+                assert_eq!(*ifdef, Span::new(7, 1, 12));
+                assert_eq!(*endif, Span::new(9, 1, 6));
+                assert_eq!(*m, Span::new(6, 1, 12));
+            }
+            r => panic!("expected conditions in a macro, got {r:?}"),
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::expr;
+use crate::lexer::{self, Token, TokenKind};
+use crate::parser::{NumberUse, ParseError};
+use crate::span::Span;
+
+//.data NAME 1, 2, 3 and .string NAME "text" fill ram before the program starts
+//each one becomes a .var reserving its words, and startup code storing the values that goes in
+//front of everything else so it runs first. a string is its chars followed by a 0
+//
+//  .data primes 2, 3   .var primes 2
+//                      @2, D=A, @primes, M=D, @3, D=A, @primes+1, M=D
+//
+//done before pseudo-instructions, which load the values too big for an a-instruction
+pub fn expand(lines: Vec<Vec<Token>>) -> (Vec<Vec<Token>>, Vec<ParseError>) {
+    let mut startup = Vec::new();
+    let mut rest = Vec::new();
+    let mut errors = Vec::new();
+
+    for line in lines {
+        let first = &line[0];
+        if first.kind != TokenKind::Word || !matches!(first.text.as_str(), ".data" | ".string") {
+            rest.push(line);
+            continue;
+        }
+
+        let call = lexer::span_of(&line).unwrap();
+        let values = match values(&line) {
+            Some(Ok(values)) => values,
+            Some(Err(e)) => {
+                errors.push(e);
+                continue;
+            }
+            None => {
+                errors.push(ParseError::InvalidDirective(first.text.clone(), call));
+                continue;
+            }
+        };
+
+        let name = &line[1];
+        for (offset, value) in values.iter().enumerate() {
+            startup.extend(store(&name.text, offset, *value, call));
+        }
+
+        rest.push(vec![
+            Token {
+                text: ".var".into(),
+                ..first.clone()
+            },
+            name.clone(),
+            Token {
+                kind: TokenKind::Word,
+                text: values.len().to_string(),
+                span: lexer::span_of(&line[2..]).unwrap(),
+            },
+        ]);
+    }
+
+    startup.extend(rest);
+    (startup, errors)
+}
+
+//the words a .data or .string puts in ram, none if it isn't written right
+fn values(line: &[Token]) -> Option<Result<Vec<u16>, ParseError>> {
+    let [directive, name, operand @ ..] = line else {
+        return None;
+    };
+
+    if !lexer::is_symbol(&name.text) || operand.is_empty() {
+        return None;
+    }
+
+    if directive.text == ".string" {
+        return match operand {
+            [s] if s.kind == TokenKind::Str => {
+                let chars = s.text.chars().map(|c| u16::try_from(u32::from(c)).ok());
+                chars.chain([Some(0)]).collect::<Option<Vec<u16>>>().map(Ok)
+            }
+            _ => None,
+        };
+    }
+
+    operand
+        .split(|t| t.is_punct(','))
+        .map(word)
+        .collect::<Option<Vec<_>>>()
+        .map(|words| words.into_iter().collect())
+}
+
+//a value of .data, a number or an expression of numbers that fits in a word
+//none for something that isn't a value at all
+fn word(value: &[Token]) -> Option<Result<u16, ParseError>> {
+    let e = match expr::parse(value) {
+        Ok(e) if e.names().is_empty() => e,
+        Ok(_) => return None,
+        Err(e) => return Some(Err(e)),
+    };
+
+    let text = lexer::text_of(value);
+    let span = lexer::span_of(value).unwrap();
+
+    Some(match e.eval(&|_| None) {
+        Ok(v @ -32768..=65535) => Ok(v as u16),
+        Ok(_) => Err(ParseError::IntegerOverflow(text, NumberUse::Data, span)),
+        Err(_) => Err(ParseError::InvalidExpression(text, span)),
+    })
+}
+
+//the instructions storing one word, 0, 1 and -1 are stored directly
+//every token points back at the .data or .string, so the listing shows them under it
+fn store(name: &str, offset: usize, value: u16, call: Span) -> Vec<Vec<Token>> {
+    let address = match offset {
+        0 => format!("@{name}"),
+        _ => format!("@{name}+{offset}"),
+    };
+
+    let text = match value {
+        0 => format!("{address}\nM=0"),
+        1 => format!("{address}\nM=1"),
+        0xFFFF => format!("{address}\nM=-1"),
+        _ => format!("@{value}\nD=A\n{address}\nM=D"),
+    };
+
+    let span = call.expanded_from(call);
+    lexer::tokenize(&text)
+        .lines
+        .into_iter()
+        .map(|line| line.into_iter().map(|t| Token { span, ..t }).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_source(src: &str) -> (Vec<String>, Vec<ParseError>) {
+        let (lines, errors) = expand(lexer::tokenize(src).lines);
+        let texts = lines.iter().map(|l| lexer::text_of(l)).collect();
+
+        (texts, errors)
+    }
+
+    #[test]
+    fn startup_code_goes_first() {
+        let src = "@5\n.data t 7, -1, 0x8000\n.string s \"hi\"\n";
+        let (texts, errors) = expand_source(src);
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            texts,
+            vec![
+                "@7", "D=A", "@t", "M=D", "@t+1", "M=-1", "@32768", "D=A", "@t+2", "M=D", "@104",
+                "D=A", "@s", "M=D", "@105", "D=A", "@s+1", "M=D", "@s+2", "M=0", "@5", ".vart3",
+                ".vars3",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let src = ".data t\n.data 5 1\n.string s 1\n.data t 1, , 2\n.data t 70000\n.data t 1/0\n";
+        let (texts, errors) = expand_source(src);
+
+        assert!(texts.is_empty());
+        match errors.as_slice() {
+            [
+                ParseError::InvalidDirective(..),
+                ParseError::InvalidDirective(..),
+                ParseError::InvalidDirective(..),
+                ParseError::InvalidExpression(..),
+                ParseError::IntegerOverflow(big, NumberUse::Data, _),
+                ParseError::InvalidExpression(div, _),
+            ] => {
+                assert_eq!(big, "70000");
+                assert_eq!(div, "1/0");
+            }
+            r => panic!("expected data errors, got {r:?}"),
+        }
+    }
+}
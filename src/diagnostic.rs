@@ -0,0 +1,748 @@
+use std::io::ErrorKind;
+
+use crate::AsmError;
+use crate::code::Code;
+use crate::macros;
+use crate::parser::{NumberUse, ParseError};
+use crate::source::Sources;
+use crate::span::Span;
+use crate::symbol::{SymbolError, SymbolWarning};
+
+//an error that can be shown to the user alongside the source it came from
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    //a warning is shown the same way but doesn't stop the program assembling
+    pub warning: bool,
+    pub message: String,
+    pub span: Span,
+    //short explanation printed under the carets
+    pub label: String,
+    pub note: Option<String>,
+    pub help: Option<String>,
+    //other places in the source the error relates to, e.g. where a label was first defined
+    pub secondary: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span, label: impl Into<String>) -> Self {
+        Diagnostic {
+            warning: false,
+            message: message.into(),
+            span,
+            label: label.into(),
+            note: None,
+            help: None,
+            secondary: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    pub fn as_warning(mut self) -> Self {
+        self.warning = true;
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.secondary.push((span, label.into()));
+        self
+    }
+
+    //errors in code from a macro also point at the call that expanded it
+    //a pseudo-instruction expands in place, so there's no separate call to show
+    fn in_expansion(self, span: Span) -> Self {
+        match span.call_site {
+            Some(call) if (call.file, call.line) != (span.file, span.line) => {
+                self.with_secondary(call.span(), "in this macro call")
+            }
+            _ => self,
+        }
+    }
+
+    //renders the diagnostic in the style of rustc, e.g.
+    //
+    //error: invalid computation `M+D+1`
+    // --> Foo.asm:3:3
+    //  |
+    //3 | D=M+D+1
+    //  |   ^^^^^ not a valid computation
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        self.render_sources(&Sources::new(file_name, source, None))
+    }
+
+    //render for code spread over several files, snippets from a file other than the error's
+    //get a ::: line naming it and an error in an included file notes the includes that led to it
+    pub fn render_sources(&self, sources: &Sources) -> String {
+        let widest = self
+            .secondary
+            .iter()
+            .map(|(s, _)| s.line)
+            .fold(self.span.line, u32::max);
+        let pad = " ".repeat(widest.to_string().len());
+
+        let level = if self.warning { "warning" } else { "error" };
+        let mut out = format!("{level}: {}\n", self.message);
+
+        //line 0 means the error isn't about any place in the source
+        if self.span.line == 0 {
+            return out;
+        }
+
+        out.push_str(&format!(
+            "{pad}--> {}:{}:{}\n",
+            sources.name(self.span.file),
+            self.span.line,
+            self.span.col
+        ));
+        out.push_str(&format!("{pad} |\n"));
+
+        //the secondary span is marked with dashes, like rustc does
+        let mut snippets = vec![(self.span, '^', self.label.as_str())];
+        for (span, label) in self.secondary.iter() {
+            snippets.push((*span, '-', label.as_str()));
+        }
+        snippets.sort_by_key(|(s, _, _)| (s.file != self.span.file, s.file, s.line, s.col));
+
+        let mut file = self.span.file;
+        for (span, marker, label) in snippets {
+            if span.file != file {
+                file = span.file;
+                out.push_str(&format!(
+                    "{pad}::: {}:{}:{}\n",
+                    sources.name(file),
+                    span.line,
+                    span.col
+                ));
+            }
+
+            out.push_str(&snippet(sources.text(file), span, marker, label, &pad));
+        }
+
+        let chain = sources.include_chain(self.span.file);
+        if self.note.is_some() || self.help.is_some() || !chain.is_empty() {
+            out.push_str(&format!("{pad} |\n"));
+        }
+
+        let mut included = self.span.file;
+        for from in chain {
+            out.push_str(&format!(
+                "{pad} = note: `{}` is included from {}:{}:{}\n",
+                sources.name(included),
+                sources.name(from.file),
+                from.line,
+                from.col
+            ));
+            included = from.file;
+        }
+
+        if let Some(note) = &self.note {
+            out.push_str(&format!("{pad} = note: {note}\n"));
+        }
+
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{pad} = help: {help}\n"));
+        }
+
+        out
+    }
+}
+
+//the source line of a span with markers under the spanned text
+fn snippet(source: &str, span: Span, marker: char, label: &str, pad: &str) -> String {
+    let line = (span.line as usize)
+        .checked_sub(1)
+        .and_then(|i| source.lines().nth(i));
+
+    let Some(line) = line else {
+        return String::new();
+    };
+    let line = line.trim_end_matches('\r');
+
+    //keep tabs in the indent so the markers line up with the source
+    let indent: String = line
+        .chars()
+        .take(span.col.saturating_sub(1) as usize)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let markers = marker.to_string().repeat(span.len.max(1) as usize);
+    let line_num = format!("{:<1$}", span.line, pad.len());
+
+    format!("{line_num} | {line}\n{pad} | {indent}{markers} {label}\n")
+}
+
+//what can be written in source, null is only used in the encoding tables
+fn valid_comps() -> &'static [&'static str] {
+    Code::comp_names()
+}
+
+fn valid_dests() -> &'static [&'static str] {
+    &Code::dest_names()[1..]
+}
+
+fn valid_jumps() -> &'static [&'static str] {
+    &Code::jump_names()[1..]
+}
+
+fn did_you_mean(input: &str, candidates: &[&'static str]) -> Option<String> {
+    suggest(input, candidates).map(|s| format!("did you mean `{s}`?"))
+}
+
+//finds the closest candidate to what the user wrote
+//the same characters in another order wins outright (M+D for D+M), otherwise the candidate
+//with the smallest edit distance is used, as long as it's close enough to be a likely typo
+pub fn suggest(input: &str, candidates: &[&'static str]) -> Option<&'static str> {
+    let upper = input.to_uppercase();
+
+    let mut sorted: Vec<char> = upper.chars().collect();
+    sorted.sort();
+
+    for &c in candidates {
+        let mut c_sorted: Vec<char> = c.chars().collect();
+        c_sorted.sort();
+
+        if c_sorted == sorted {
+            return Some(c);
+        }
+    }
+
+    let max_distance = (upper.chars().count() / 2).max(1);
+
+    candidates
+        .iter()
+        .map(|&c| (edit_distance(&upper, c), c))
+        .filter(|&(d, _)| d <= max_distance)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, c)| c)
+}
+
+//levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Self {
+        match e {
+            ParseError::InvalidLabel(l, span) => {
+                Diagnostic::new(format!("invalid label `{l}`"), *span, "not a valid label")
+                    .with_note("labels are written `(NAME)`, and the name cannot start with a digit")
+            }
+
+            ParseError::InvalidDest(d, span) => Diagnostic::new(
+                format!("invalid destination `{d}`"),
+                *span,
+                "not a valid destination",
+            )
+            .with_note("a destination is any combination of A, D and M, each used at most once")
+            .with_help(did_you_mean(d, valid_dests())),
+
+            ParseError::InvalidCmp(c, span) => Diagnostic::new(
+                format!("invalid computation `{c}`"),
+                *span,
+                "not a valid computation",
+            )
+            .with_note(
+                "the computation must be one of the hack alu operations, such as `D+1`, `M-D` or `D&A`",
+            )
+            .with_help(did_you_mean(c, valid_comps())),
+
+            ParseError::InvalidJmp(j, span) => {
+                Diagnostic::new(format!("invalid jump `{j}`"), *span, "not a valid jump")
+                    .with_note("a jump is one of JGT, JEQ, JGE, JLT, JNE, JLE or JMP")
+                    .with_help(did_you_mean(j, valid_jumps()))
+            }
+
+            ParseError::InvalidCommand(c, span) => Diagnostic::new(
+                format!("unrecognised command `{c}`"),
+                *span,
+                "not an instruction or label",
+            )
+            .with_note("expected `@value`, `(LABEL)` or `dest=comp;jump`"),
+
+            ParseError::IntegerOverflow(i, used, span) => {
+                let (label, note) = match used {
+                    NumberUse::AInstruction => (
+                        "does not fit in an a-instruction",
+                        "a-instruction constants must be between -32768 and 65535, \
+                         anything outside 0 to 32767 takes two instructions",
+                    ),
+                    NumberUse::Data => (
+                        "does not fit in a word",
+                        ".data values must be between -32768 and 65535",
+                    ),
+                    NumberUse::Constant => (
+                        "too large for a constant",
+                        ".equ values must be between 0 and 32767, so they can be loaded with `@`",
+                    ),
+                    NumberUse::VarSize => (
+                        "too many words",
+                        "a .var can be at most 32767 words, and has to fit below the screen",
+                    ),
+                    NumberUse::VarAddress => (
+                        "not an address variables can be placed at",
+                        ".var addresses must be between 0 and 32767",
+                    ),
+                };
+
+                Diagnostic::new(format!("number `{i}` is too large"), *span, label).with_note(note)
+            }
+
+            ParseError::InvalidNumber(n, span) => {
+                Diagnostic::new(format!("invalid number `{n}`"), *span, "not a valid number")
+                    .with_note("numbers are decimal, hex like `0x4000` or binary like `0b1010`")
+            }
+
+            ParseError::InvalidExpression(e, span) => Diagnostic::new(
+                format!("invalid expression `{e}`"),
+                *span,
+                "unexpected here",
+            )
+            .with_note(
+                "an expression uses numbers, 'c' characters, names, brackets and + - * /",
+            ),
+
+            ParseError::UnterminatedComment(span) => Diagnostic::new(
+                "unterminated block comment",
+                *span,
+                "comment starts here",
+            )
+            .with_note("block comments are closed with `*/`"),
+
+            ParseError::UnterminatedString(span) => Diagnostic::new(
+                "unterminated string",
+                *span,
+                "string starts here",
+            )
+            .with_note("strings are closed with `\"` on the same line"),
+
+            ParseError::EndOfFile(span) => {
+                Diagnostic::new("unexpected end of file", *span, "expected a command")
+            }
+
+            ParseError::InvalidDirective(d, span) => Diagnostic::new(
+                format!("invalid directive `{d}`"),
+                *span,
+                "not a valid directive here",
+            )
+            .with_note(
+                "expected `.equ NAME value`, `.define NAME value`, `.alias NAME TARGET`, \
+                 `.var NAME SIZE @ ADDR`, `.data NAME values`, `.string NAME \"TEXT\"`, \
+                 `.include \"FILE\"`, `.macro NAME params`, `.endm`, `.if COND`, `.else` \
+                 or `.endif`",
+            ),
+
+            ParseError::UnterminatedMacro(m, span) => Diagnostic::new(
+                format!("macro `{m}` is never closed"),
+                *span,
+                "macro starts here",
+            )
+            .with_note("a macro body ends with `.endm` on its own line"),
+
+            ParseError::DuplicateMacro(m, span, first) => Diagnostic::new(
+                format!("macro `{m}` is defined more than once"),
+                *span,
+                "redefined here",
+            )
+            .with_secondary(*first, "first defined here"),
+
+            ParseError::ReservedMacroName(m, span) => Diagnostic::new(
+                format!("`{m}` can't be used as a macro name"),
+                *span,
+                "reserved name",
+            )
+            .with_note(
+                "a macro can't be named like a dest, comp or jump mnemonic, `goto`, `if` or a directive",
+            ),
+
+            ParseError::MacroArguments(m, expected, found, span) => Diagnostic::new(
+                format!(
+                    "macro `{m}` takes {expected} argument{} but {found} {} given",
+                    if *expected == 1 { "" } else { "s" },
+                    if *found == 1 { "was" } else { "were" }
+                ),
+                *span,
+                "wrong number of arguments",
+            )
+            .with_note("arguments are separated by commas, e.g. `NAME a, b`"),
+
+            ParseError::RecursiveMacro(m, span) => Diagnostic::new(
+                format!("macro `{m}` calls itself"),
+                *span,
+                "called again here",
+            )
+            .with_note("macros can call each other, but not themselves, directly or indirectly"),
+
+            ParseError::MacroTooLarge(m, span) => Diagnostic::new(
+                format!("macro `{m}` expands to too much code"),
+                *span,
+                "expansion stopped here",
+            )
+            .with_note(format!(
+                "all macro expansions together can make at most {} lines",
+                macros::MAX_EXPANDED_LINES
+            )),
+
+            ParseError::IncludeFailed(path, ErrorKind::NotFound, span) => Diagnostic::new(
+                format!("cannot find `{path}` to include"),
+                *span,
+                "file not found",
+            )
+            .with_note(
+                "included files are looked for next to the file including them, \
+                 then in each -I directory",
+            ),
+
+            ParseError::IncludeFailed(path, kind, span) => Diagnostic::new(
+                format!("cannot include `{path}`: {kind}"),
+                *span,
+                "file could not be read",
+            ),
+
+            ParseError::RecursiveInclude(path, span) => Diagnostic::new(
+                format!("`{path}` includes itself"),
+                *span,
+                "already being included",
+            )
+            .with_note("a file can't be included again by a file it includes"),
+
+            ParseError::InvalidCondition(span) => {
+                Diagnostic::new("invalid condition", *span, "can't be evaluated")
+                    .with_note(
+                        "expected `.if VALUE`, `.if VALUE OP VALUE` with one of == != < <= > >=, \
+                         `.ifdef NAME` or `.ifndef NAME`",
+                    )
+            }
+
+            ParseError::UndefinedCondition(name, span) => Diagnostic::new(
+                format!("`{name}` is not defined"),
+                *span,
+                "used in a condition",
+            )
+            .with_note(
+                "a condition can use predefined symbols, -D definitions and \
+                 constants from an earlier .equ or .define",
+            ),
+
+            ParseError::UnterminatedIf(span) => {
+                Diagnostic::new("conditional is never closed", *span, "opened here")
+                    .with_note("every .if, .ifdef and .ifndef needs an .endif")
+            }
+
+            ParseError::ConditionInMacro(span, m) => Diagnostic::new(
+                "conditional assembly inside a macro",
+                *span,
+                "not allowed in a macro body",
+            )
+            .with_secondary(*m, "in this macro")
+            .with_note(
+                "conditions are decided before macros are expanded, \
+                 put the .if around the whole .macro instead",
+            ),
+
+            ParseError::InvalidPseudo(keyword, span) => {
+                let (message, note) = match keyword.as_str() {
+                    "goto" => ("invalid `goto`", "expected `goto LABEL`"),
+                    "if" => (
+                        "invalid conditional jump",
+                        "expected `if COMP OP 0 goto LABEL`, e.g. `if D>0 goto LOOP`, \
+                         with one of == != < <= > >=",
+                    ),
+                    _ => (
+                        "invalid memory access",
+                        "expected `DEST=[addr]` to read memory or `[addr]=COMP` to write it",
+                    ),
+                };
+
+                Diagnostic::new(message, *span, "not a valid pseudo-instruction").with_note(note)
+            }
+
+            ParseError::ClobberedComp(c, span) => Diagnostic::new(
+                format!("computation `{c}` can't be used here"),
+                *span,
+                "reads A or M",
+            )
+            .with_note("A is loaded with the address first, so only D and constants are left"),
+        }
+        .in_expansion(e.span())
+    }
+}
+
+impl From<&SymbolError> for Diagnostic {
+    fn from(e: &SymbolError) -> Self {
+        match e {
+            SymbolError::InvalidSymbol(s, span) => {
+                Diagnostic::new(format!("invalid symbol `{s}`"), *span, "not a valid symbol")
+            }
+
+            SymbolError::OutofBoundsAddress(s, base, limit, span) => Diagnostic::new(
+                format!("no memory left for variable `{s}`"),
+                *span,
+                "variable does not fit in ram",
+            )
+            .with_note(format!(
+                "variables are placed from RAM[{base}] up to RAM[{limit}]"
+            )),
+
+            SymbolError::UnresolvedSymbol(s, span) => Diagnostic::new(
+                format!("undefined symbol `{s}`"),
+                *span,
+                "not a label or predefined symbol",
+            )
+            .with_note("strict mode is on, so unknown names are not turned into variables"),
+
+            SymbolError::UnresolvedInExpression(s, span) => Diagnostic::new(
+                format!("undefined symbol `{s}` in an expression"),
+                *span,
+                "not a label, constant or .var",
+            )
+            .with_note(format!(
+                "only a plain `@{s}` makes a variable, and it is one word, \
+                 use `.var {s} SIZE` to reserve the words after it"
+            )),
+
+            SymbolError::DuplicateSymbol(l, span, first) => Diagnostic::new(
+                format!("`{l}` is defined more than once"),
+                *span,
+                "redefined here",
+            )
+            .with_secondary(*first, "first defined here")
+            .with_note("a name can only be given to one label, constant, alias or variable"),
+
+            SymbolError::RedefinedPredefined(l, span) => Diagnostic::new(
+                format!("`{l}` redefines a predefined symbol"),
+                *span,
+                "predefined symbol defined again",
+            )
+            .with_note("R0-R15, SP, LCL, ARG, THIS, THAT, SCREEN and KBD are reserved"),
+
+            SymbolError::RedefinedDefine(l, span) => Diagnostic::new(
+                format!("`{l}` is already defined on the command line"),
+                *span,
+                "defined again here",
+            )
+            .with_note(format!(
+                "`-D {l}` defines it before the source is read, so it can't also be defined here"
+            )),
+
+            SymbolError::UndefinedAlias(target, span) => Diagnostic::new(
+                format!("alias to undefined symbol `{target}`"),
+                *span,
+                "the target is not defined",
+            )
+            .with_note("an alias can name a predefined symbol, label, constant or another alias"),
+
+            SymbolError::OutOfRange(v, span) => Diagnostic::new(
+                format!("value {v} doesn't fit in an a-instruction"),
+                *span,
+                "out of range",
+            )
+            .with_note(
+                "a-instruction values must be between 0 and 32767, only an expression \
+                 without names can be loaded outside that",
+            ),
+
+            SymbolError::DivisionByZero(span) => {
+                Diagnostic::new("division by zero", *span, "divides by zero")
+            }
+
+            SymbolError::VariableOverlap(v, span, other) => Diagnostic::new(
+                format!("variable `{v}` overlaps another variable"),
+                *span,
+                "placed here",
+            )
+            .with_secondary(*other, "overlaps this one")
+            .with_note("variables placed with `.var NAME SIZE @ ADDR` can't share memory"),
+
+            SymbolError::LabelOutOfRange(l, address, span) => Diagnostic::new(
+                format!("label `{l}` is at rom address {address}, too far to jump to"),
+                *span,
+                "defined here",
+            )
+            .with_note("an a-instruction can only load addresses up to 32767"),
+
+            SymbolError::ReservedMemory(v, limit, span) => Diagnostic::new(
+                format!("variable `{v}` is placed past the memory for variables"),
+                *span,
+                "placed here",
+            )
+            .with_note(format!("variables have to end at or before RAM[{limit}]")),
+        }
+        .in_expansion(e.span())
+    }
+}
+
+impl From<&SymbolWarning> for Diagnostic {
+    fn from(w: &SymbolWarning) -> Self {
+        match w {
+            SymbolWarning::LabelAsData(l, span, label) => Diagnostic::new(
+                format!("label `{l}` is used as a variable"),
+                *span,
+                "memory at this label is read or written",
+            )
+            .with_secondary(*label, "defined as a jump label here")
+            .with_note("a label is an address in rom, so `M` after `@LABEL` touches unrelated ram")
+            .as_warning(),
+        }
+    }
+}
+
+impl From<&AsmError> for Diagnostic {
+    fn from(e: &AsmError) -> Self {
+        match e {
+            AsmError::Parse(e) => e.into(),
+            AsmError::Symbol(e) => e.into(),
+            AsmError::Read(kind) => Diagnostic::new(
+                format!("could not read source: {kind}"),
+                Span::default(),
+                "",
+            ),
+
+            AsmError::Io(kind) => Diagnostic::new(
+                format!("could not write output: {kind}"),
+                Span::default(),
+                "",
+            ),
+
+            AsmError::ProgramTooLarge(words, rom_size) => Diagnostic::new(
+                format!("program is {words} words but the rom only holds {rom_size}"),
+                Span::default(),
+                "",
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source::SourceFile;
+
+    #[test]
+    fn suggest_reordered_comp() {
+        assert_eq!(suggest("M+D", valid_comps()), Some("D+M"));
+        assert_eq!(suggest("A&D", valid_comps()), Some("D&A"));
+    }
+
+    #[test]
+    fn suggest_typos() {
+        assert_eq!(suggest("JPM", valid_jumps()), Some("JMP"));
+        assert_eq!(suggest("jeq", valid_jumps()), Some("JEQ"));
+        assert_eq!(suggest("X", valid_dests()), Some("M"));
+        assert_eq!(suggest("QWERTY", valid_comps()), None);
+    }
+
+    #[test]
+    fn render_invalid_comp() {
+        let source = "@2\n  D=M*2\n";
+        let err = ParseError::InvalidCmp("M*2".into(), Span::new(2, 5, 3));
+
+        let out = Diagnostic::from(&err).render("Foo.asm", source);
+
+        assert_eq!(
+            out,
+            "error: invalid computation `M*2`\n \
+             --> Foo.asm:2:5\n  \
+             |\n\
+             2 |   D=M*2\n  \
+             |     ^^^ not a valid computation\n  \
+             |\n  \
+             = note: the computation must be one of the hack alu operations, such as `D+1`, `M-D` or `D&A`\n"
+        );
+    }
+
+    #[test]
+    fn render_with_suggestion() {
+        let source = "D=M+D\n";
+        let err = ParseError::InvalidCmp("M+D".into(), Span::new(1, 3, 3));
+
+        let out = Diagnostic::from(&err).render("Foo.asm", source);
+
+        assert!(out.contains("1 | D=M+D\n"));
+        assert!(out.contains("  |   ^^^ not a valid computation\n"));
+        assert!(out.ends_with("  = help: did you mean `D+M`?\n"));
+    }
+
+    #[test]
+    fn render_macro_expansion() {
+        let source = ".macro BAD\n  D=M*2\n.endm\nBAD\n";
+        let span = Span::new(2, 5, 3).expanded_from(Span::new(4, 1, 3));
+        let err = ParseError::InvalidCmp("M*2".into(), span);
+
+        let out = Diagnostic::from(&err).render("Foo.asm", source);
+
+        assert!(out.contains(
+            "2 |   D=M*2\n  |     ^^^ not a valid computation\n\
+             4 | BAD\n  | --- in this macro call\n"
+        ));
+    }
+
+    #[test]
+    fn render_secondary_span() {
+        let source = "(LOOP)\n@1\n(LOOP)\n";
+        let err =
+            SymbolError::DuplicateSymbol("LOOP".into(), Span::new(3, 2, 4), Span::new(1, 2, 4));
+
+        let out = Diagnostic::from(&err).render("Foo.asm", source);
+
+        assert!(out.contains(
+            "1 | (LOOP)\n  |  ---- first defined here\n3 | (LOOP)\n  |  ^^^^ redefined here\n"
+        ));
+    }
+
+    #[test]
+    fn render_warning() {
+        let source = "(END)\n@END\nM=0\n";
+        let warning =
+            SymbolWarning::LabelAsData("END".into(), Span::new(2, 2, 3), Span::new(1, 2, 3));
+
+        let out = Diagnostic::from(&warning).render("Foo.asm", source);
+
+        assert!(out.starts_with("warning: label `END` is used as a variable\n"));
+    }
+
+    #[test]
+    fn render_included_file() {
+        let mut sources = Sources::new("main.asm", "(LOOP)\n.include \"lib.asm\"\n", None);
+        sources.add(SourceFile {
+            name: "lib.asm".into(),
+            text: "@1\n(LOOP)\n".into(),
+            path: None,
+            included_from: Some(Span::new(2, 1, 17)),
+        });
+        let err = SymbolError::DuplicateSymbol(
+            "LOOP".into(),
+            Span::new(2, 2, 4).in_file(1),
+            Span::new(1, 2, 4),
+        );
+
+        let out = Diagnostic::from(&err).render_sources(&sources);
+
+        assert!(out.contains(
+            " --> lib.asm:2:2\n  |\n2 | (LOOP)\n  |  ^^^^ redefined here\n\
+             \x20::: main.asm:1:2\n1 | (LOOP)\n  |  ---- first defined here\n"
+        ));
+        assert!(out.contains("  = note: `lib.asm` is included from main.asm:2:1\n"));
+    }
+}
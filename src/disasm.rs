@@ -0,0 +1,129 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::instruction::{Instruction, Jump};
+
+#[derive(Debug, Clone)]
+pub enum DisasmError {
+    //instruction word and the rom address it was found at
+    InvalidInstruction(u16, u16),
+}
+
+impl fmt::Display for DisasmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisasmError::InvalidInstruction(word, addr) => {
+                write!(f, "{word:016b} at rom address {addr} is not an instruction")
+            }
+        }
+    }
+}
+
+pub fn label_name(addr: u16) -> String {
+    format!("L_{:04}", addr)
+}
+
+//turns machine words back into hack assembly
+//an @value directly before a jumping c-instruction is taken as a jump target and given a label
+pub fn disassemble(words: &[u16]) -> Result<String, DisasmError> {
+    let decoded = words
+        .iter()
+        .enumerate()
+        .map(|(i, &w)| {
+            Instruction::decode(w).map_err(|_| DisasmError::InvalidInstruction(w, i as u16))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    //addresses of a-instructions that load a jump target
+    let mut jump_loads = BTreeSet::new();
+    let mut targets = BTreeSet::new();
+
+    for (i, pair) in decoded.windows(2).enumerate() {
+        if let [Instruction::A(v), Instruction::C { jump, .. }] = pair {
+            //a label can sit one past the last instruction
+            if *jump != Jump::Null && (*v as usize) <= words.len() {
+                jump_loads.insert(i);
+                targets.insert(*v as usize);
+            }
+        }
+    }
+
+    let mut out = String::new();
+
+    for (i, d) in decoded.iter().enumerate() {
+        if targets.contains(&i) {
+            out.push_str(&format!("({})\n", label_name(i as u16)));
+        }
+
+        match d {
+            Instruction::A(v) if jump_loads.contains(&i) => {
+                out.push_str(&format!("@{}\n", label_name(*v)));
+            }
+
+            _ => out.push_str(&format!("{d}\n")),
+        }
+    }
+
+    if targets.contains(&words.len()) {
+        out.push_str(&format!("({})\n", label_name(words.len() as u16)));
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Assembler;
+    use crate::hack;
+    use std::fs;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    #[test]
+    fn disassemble_add() {
+        let words = [2, 0b1110110000010000, 3, 0b1110000010010000, 0, 0b1110001100001000];
+
+        let src = disassemble(&words).unwrap();
+
+        assert_eq!(src, "@2\nD=A\n@3\nD=D+A\n@0\nM=D\n");
+    }
+
+    #[test]
+    fn disassemble_labels() {
+        //@0 D;JGT @5 0;JMP @3, the two jump targets get labels, 5 being one past the end
+        //the last @3 isn't followed by a jump so it stays a number
+        let words = [0, 0b1110001100000001, 5, 0b1110101010000111, 3];
+
+        let src = disassemble(&words).unwrap();
+
+        assert_eq!(
+            src,
+            "(L_0000)\n@L_0000\nD;JGT\n@L_0005\n0;JMP\n@3\n(L_0005)\n"
+        );
+    }
+
+    #[test]
+    fn disassemble_invalid() {
+        match disassemble(&[0, 0b1010101010000111]) {
+            Err(DisasmError::InvalidInstruction(_, 1)) => {}
+            r => panic!("expected invalid instruction, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn pong_round_trip() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("asm");
+        path.push("Pong.hack");
+
+        let file = fs::File::open(path).expect("failed to open hack file");
+        let words = hack::read_words(BufReader::new(file)).unwrap();
+
+        let src = disassemble(&words).unwrap();
+
+        let reassembled = Assembler::new(src.as_bytes()).assemble_to_words().unwrap();
+
+        assert_eq!(words, reassembled);
+    }
+}
@@ -0,0 +1,335 @@
+use std::io::BufRead;
+
+use crate::hack::{self, HackError};
+use crate::instruction::{Comp, Dest, Instruction};
+
+pub const ROM_SIZE: usize = 32768;
+pub const RAM_SIZE: usize = 32768;
+pub const SCREEN: u16 = 16384;
+pub const SCREEN_SIZE: usize = 8192;
+pub const KBD: u16 = 24576;
+
+#[derive(Debug, Clone)]
+pub enum EmulatorError {
+    ProgramTooLarge(usize),
+    InvalidHack(HackError),
+    //instruction word and the rom address it was found at
+    InvalidInstruction(u16, u16),
+    //address in A and the rom address of the instruction that used it
+    AddressOutOfBounds(u16, u16),
+    PcOutOfBounds(u16),
+}
+
+impl From<HackError> for EmulatorError {
+    fn from(e: HackError) -> Self {
+        EmulatorError::InvalidHack(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    //the program jumped into the usual `(END) @END 0;JMP` loop
+    Halted,
+    //pc ran past the last loaded instruction
+    EndOfProgram,
+    StepLimit,
+}
+
+pub struct Machine {
+    rom: Vec<u16>,
+    ram: Vec<u16>,
+    a: u16,
+    d: u16,
+    pc: u16,
+    program_len: usize,
+    steps: u64,
+    halted: bool,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Machine {
+            rom: vec![0; ROM_SIZE],
+            ram: vec![0; RAM_SIZE],
+            a: 0,
+            d: 0,
+            pc: 0,
+            program_len: 0,
+            steps: 0,
+            halted: false,
+        }
+    }
+
+    //loads a program into rom and resets the machine
+    pub fn load(&mut self, program: &[u16]) -> Result<(), EmulatorError> {
+        if program.len() > ROM_SIZE {
+            return Err(EmulatorError::ProgramTooLarge(program.len()));
+        }
+
+        self.rom.fill(0);
+        self.rom[..program.len()].copy_from_slice(program);
+        self.program_len = program.len();
+        self.reset();
+
+        Ok(())
+    }
+
+    //loads .hack text, the format written by Assembler::assemble
+    pub fn load_hack<R: BufRead>(&mut self, reader: R) -> Result<(), EmulatorError> {
+        let words = hack::read_words(reader)?;
+        self.load(&words)
+    }
+
+    pub fn reset(&mut self) {
+        self.ram.fill(0);
+        self.a = 0;
+        self.d = 0;
+        self.pc = 0;
+        self.steps = 0;
+        self.halted = false;
+    }
+
+    pub fn a(&self) -> u16 {
+        self.a
+    }
+
+    pub fn d(&self) -> u16 {
+        self.d
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn rom(&self) -> &[u16] {
+        &self.rom[..self.program_len]
+    }
+
+    pub fn ram(&self) -> &[u16] {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u16] {
+        &mut self.ram
+    }
+
+    pub fn screen(&self) -> &[u16] {
+        let start = SCREEN as usize;
+        &self.ram[start..start + SCREEN_SIZE]
+    }
+
+    pub fn keyboard(&self) -> u16 {
+        self.ram[KBD as usize]
+    }
+
+    pub fn set_keyboard(&mut self, key: u16) {
+        self.ram[KBD as usize] = key;
+    }
+
+    //executes the instruction at pc
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        let pc = self.pc;
+        let instr = *self
+            .rom
+            .get(pc as usize)
+            .ok_or(EmulatorError::PcOutOfBounds(pc))?;
+
+        //decoding goes through the same tables as the assembler
+        let (dest, comp, jump) = match Instruction::decode(instr) {
+            Ok(Instruction::A(v)) => {
+                self.a = v;
+                self.pc = pc.wrapping_add(1);
+                self.steps += 1;
+                return Ok(());
+            }
+
+            Ok(Instruction::C { dest, comp, jump }) => (dest, comp, jump),
+
+            Err(_) => return Err(EmulatorError::InvalidInstruction(instr, pc)),
+        };
+
+        let uses_m = comp.uses_m() || dest.has_m();
+        if uses_m && self.a as usize >= RAM_SIZE {
+            return Err(EmulatorError::AddressOutOfBounds(self.a, pc));
+        }
+
+        let x = self.d;
+        let y = if comp.uses_m() {
+            self.ram[self.a as usize]
+        } else {
+            self.a
+        };
+
+        let out = match comp {
+            Comp::Zero => 0,
+            Comp::One => 1,
+            Comp::NegOne => 0xFFFF,
+            Comp::D => x,
+            Comp::A | Comp::M => y,
+            Comp::NotD => !x,
+            Comp::NotA | Comp::NotM => !y,
+            Comp::NegD => x.wrapping_neg(),
+            Comp::NegA | Comp::NegM => y.wrapping_neg(),
+            Comp::DPlusOne => x.wrapping_add(1),
+            Comp::APlusOne | Comp::MPlusOne => y.wrapping_add(1),
+            Comp::DMinusOne => x.wrapping_sub(1),
+            Comp::AMinusOne | Comp::MMinusOne => y.wrapping_sub(1),
+            Comp::DPlusA | Comp::DPlusM => x.wrapping_add(y),
+            Comp::DMinusA | Comp::DMinusM => x.wrapping_sub(y),
+            Comp::AMinusD | Comp::MMinusD => y.wrapping_sub(x),
+            Comp::DAndA | Comp::DAndM => x & y,
+            Comp::DOrA | Comp::DOrM => x | y,
+        };
+
+        let jumped = jump.taken(out as i16);
+
+        //memory and the jump both use the value of A from before this instruction
+        let target = self.a;
+        if dest.has_m() {
+            self.ram[target as usize] = out;
+        }
+        if dest.has_a() {
+            self.a = out;
+        }
+        if dest.has_d() {
+            self.d = out;
+        }
+
+        if jumped {
+            //a jump with no side effects back onto itself, or onto the @ that loads its own address,
+            //can never leave the loop
+            let self_loop = target == pc
+                || (pc > 0 && target == pc - 1 && self.rom[target as usize] == target);
+            self.halted = dest == Dest::Null && self_loop;
+            self.pc = target;
+        } else {
+            self.pc = pc.wrapping_add(1);
+        }
+
+        self.steps += 1;
+        Ok(())
+    }
+
+    //runs until the program halts, runs off the end of rom, or max_steps instructions have executed
+    pub fn run(&mut self, max_steps: u64) -> Result<Stop, EmulatorError> {
+        let mut count = 0;
+
+        loop {
+            if self.halted {
+                return Ok(Stop::Halted);
+            }
+
+            if self.pc as usize >= self.program_len {
+                return Ok(Stop::EndOfProgram);
+            }
+
+            if count == max_steps {
+                return Ok(Stop::StepLimit);
+            }
+
+            self.step()?;
+            count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    fn load_file(name: &str) -> Machine {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("asm");
+        path.push(name);
+
+        let file = fs::File::open(path).expect("failed to open hack file");
+
+        let mut machine = Machine::new();
+        machine.load_hack(BufReader::new(file)).unwrap();
+        machine
+    }
+
+    #[test]
+    fn run_add() {
+        let mut machine = load_file("Add.hack");
+
+        assert_eq!(machine.run(100).unwrap(), Stop::EndOfProgram);
+        assert_eq!(machine.ram()[0], 5);
+        assert_eq!(machine.steps(), 6);
+    }
+
+    #[test]
+    fn run_halts_on_end_loop() {
+        //@7 D=A @0 M=D (END) @4 0;JMP
+        let program = [7, 0b1110110000010000, 0, 0b1110001100001000, 4, 0b1110101010000111];
+
+        let mut machine = Machine::new();
+        machine.load(&program).unwrap();
+
+        assert_eq!(machine.run(1000).unwrap(), Stop::Halted);
+        assert_eq!(machine.ram()[0], 7);
+        assert_eq!(machine.pc(), 4);
+    }
+
+    #[test]
+    fn conditional_jump_and_memory() {
+        //counts R0 down to zero, adding 2 to R1 each time round the loop
+        let program = [
+            0,                  // (LOOP) @0
+            0b1111110000010000, // D=M
+            11,                 // @END
+            0b1110001100000010, // D;JEQ
+            0,                  // @0
+            0b1111110010001000, // M=M-1
+            1,                  // @1
+            0b1111110111001000, // M=M+1
+            0b1111110111001000, // M=M+1
+            0,                  // @LOOP
+            0b1110101010000111, // 0;JMP
+        ];
+
+        let mut machine = Machine::new();
+        machine.load(&program).unwrap();
+        machine.ram_mut()[0] = 3;
+
+        assert_eq!(machine.run(1000).unwrap(), Stop::EndOfProgram);
+        assert_eq!(machine.ram()[0], 0);
+        assert_eq!(machine.ram()[1], 6);
+    }
+
+    #[test]
+    fn invalid_comp_is_rejected() {
+        let mut machine = Machine::new();
+        machine.load(&[0b1111111111000000]).unwrap();
+
+        match machine.step() {
+            Err(EmulatorError::InvalidInstruction(_, 0)) => {}
+            r => panic!("expected invalid instruction, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn run_pong_draws_to_screen() {
+        let mut machine = load_file("Pong.hack");
+
+        assert_eq!(machine.run(5_000_000).unwrap(), Stop::StepLimit);
+        assert!(machine.screen().iter().any(|&w| w != 0));
+    }
+}
@@ -0,0 +1,296 @@
+use std::fmt;
+
+use crate::lexer::{self, Token, TokenKind};
+use crate::parser::ParseError;
+use crate::span::Span;
+
+//an expression in an a-instruction, e.g. @SCREEN+32*5
+//it's worked out once the symbol table is built, so it can use labels and variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(u32),
+    Name(String, Span),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    //a name with no address, the name and where it's used
+    Undefined(String, Span),
+    DivisionByZero,
+}
+
+impl Op {
+    fn from_punct(token: &Token) -> Option<Op> {
+        match token.kind {
+            TokenKind::Punct('+') => Some(Op::Add),
+            TokenKind::Punct('-') => Some(Op::Sub),
+            TokenKind::Punct('*') => Some(Op::Mul),
+            TokenKind::Punct('/') => Some(Op::Div),
+            _ => None,
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+        }
+    }
+}
+
+impl Expr {
+    //every name used, in the order they are written
+    pub fn names(&self) -> Vec<(&str, Span)> {
+        match self {
+            Expr::Number(_) => Vec::new(),
+            Expr::Name(n, span) => vec![(n.as_str(), *span)],
+            Expr::Neg(e) => e.names(),
+            Expr::Binary(_, l, r) => {
+                let mut names = l.names();
+                names.extend(r.names());
+                names
+            }
+        }
+    }
+
+    //the value with each name replaced by its address
+    //it can be anything here, the caller checks it fits in an a-instruction
+    //the arithmetic saturates, anything that big is out of range anyway
+    pub fn eval(&self, lookup: &impl Fn(&str) -> Option<u16>) -> Result<i64, EvalError> {
+        match self {
+            Expr::Number(n) => Ok(*n as i64),
+
+            Expr::Name(n, span) => lookup(n)
+                .map(i64::from)
+                .ok_or_else(|| EvalError::Undefined(n.clone(), *span)),
+
+            Expr::Neg(e) => Ok(e.eval(lookup)?.saturating_neg()),
+
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (l.eval(lookup)?, r.eval(lookup)?);
+
+                match op {
+                    Op::Add => Ok(l.saturating_add(r)),
+                    Op::Sub => Ok(l.saturating_sub(r)),
+                    Op::Mul => Ok(l.saturating_mul(r)),
+                    Op::Div => l.checked_div(r).ok_or(EvalError::DivisionByZero),
+                }
+            }
+        }
+    }
+}
+
+//formats as it would be written, with brackets only where they are needed
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(n) => write!(f, "{n}"),
+            Expr::Name(n, _) => f.write_str(n),
+            Expr::Neg(e) if matches!(**e, Expr::Binary(..)) => write!(f, "-({e})"),
+            Expr::Neg(e) => write!(f, "-{e}"),
+
+            Expr::Binary(op, l, r) => {
+                //a-b-c is (a-b)-c, so only the right side needs brackets at the same precedence
+                let wrap = |e: &Expr, right: bool| match e {
+                    Expr::Binary(inner, ..) => {
+                        inner.precedence() < op.precedence()
+                            || (right && inner.precedence() == op.precedence())
+                    }
+                    _ => false,
+                };
+
+                if wrap(l, false) {
+                    write!(f, "({l})")?;
+                } else {
+                    write!(f, "{l}")?;
+                }
+
+                write!(f, "{}", op.symbol())?;
+
+                if wrap(r, true) {
+                    write!(f, "({r})")
+                } else {
+                    write!(f, "{r}")
+                }
+            }
+        }
+    }
+}
+
+//parses the tokens after the @ of an a-instruction
+//
+//  expr    = term (('+' | '-') term)*
+//  term    = unary (('*' | '/') unary)*
+//  unary   = '-' unary | primary
+//  primary = number | 'c' | name | '(' expr ')'
+pub fn parse(tokens: &[Token]) -> Result<Expr, ParseError> {
+    let mut p = ExprParser { tokens, pos: 0 };
+    let e = p.expr(1)?;
+
+    match tokens.get(p.pos) {
+        None => Ok(e),
+        Some(t) => Err(p.invalid(t.span)),
+    }
+}
+
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl ExprParser<'_> {
+    fn invalid(&self, span: Span) -> ParseError {
+        ParseError::InvalidExpression(lexer::text_of(self.tokens), span)
+    }
+
+    //binary operators of at least min_precedence, all of them are left associative
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut left = self.unary()?;
+
+        while let Some(op) = self
+            .tokens
+            .get(self.pos)
+            .and_then(Op::from_punct)
+            .filter(|op| op.precedence() >= min_precedence)
+        {
+            self.pos += 1;
+            let right = self.expr(op.precedence() + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let Some(t) = self.tokens.get(self.pos) else {
+            //ran out of tokens, e.g. @SCREEN+
+            let end = self
+                .tokens
+                .last()
+                .map_or(Span::default(), |t| t.span.after());
+            return Err(self.invalid(end));
+        };
+        self.pos += 1;
+
+        match t.kind {
+            TokenKind::Punct('-') => Ok(Expr::Neg(Box::new(self.unary()?))),
+
+            TokenKind::Punct('(') => {
+                let e = self.expr(1)?;
+
+                match self.tokens.get(self.pos) {
+                    Some(close) if close.is_punct(')') => {
+                        self.pos += 1;
+                        Ok(e)
+                    }
+                    Some(t) => Err(self.invalid(t.span)),
+                    None => Err(self.invalid(t.span)),
+                }
+            }
+
+            _ if lexer::is_number(t) => lexer::number_value(t)
+                .map(Expr::Number)
+                .ok_or_else(|| ParseError::InvalidNumber(t.text.clone(), t.span)),
+
+            TokenKind::Word => Ok(Expr::Name(t.text.clone(), t.span)),
+
+            _ => Err(self.invalid(t.span)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse_str(src: &str) -> Result<Expr, ParseError> {
+        parse(&lexer::tokenize(src).lines[0])
+    }
+
+    fn eval_str(src: &str) -> Result<i64, EvalError> {
+        let lookup = |n: &str| match n {
+            "SCREEN" => Some(16384),
+            "KBD" => Some(24576),
+            "table" => Some(16),
+            _ => None,
+        };
+
+        parse_str(src).unwrap().eval(&lookup)
+    }
+
+    #[test]
+    fn precedence_and_literals() {
+        assert_eq!(eval_str("SCREEN+32*5"), Ok(16544));
+        assert_eq!(eval_str("KBD-1"), Ok(24575));
+        assert_eq!(eval_str("(table+3)*2"), Ok(38));
+        assert_eq!(eval_str("10-4-3"), Ok(3));
+        assert_eq!(eval_str("0x4000+0b101-'A'"), Ok(16384 + 5 - 65));
+        assert_eq!(eval_str("-table+20/3"), Ok(-10));
+    }
+
+    #[test]
+    fn eval_errors() {
+        assert_eq!(eval_str("1/(table-16)"), Err(EvalError::DivisionByZero));
+        assert_eq!(
+            eval_str("SCREEN+nope"),
+            Err(EvalError::Undefined("nope".into(), Span::new(1, 8, 4)))
+        );
+    }
+
+    #[test]
+    fn display() {
+        for src in [
+            "SCREEN+32*5",
+            "(a+b)*c",
+            "a-(b-c)",
+            "a-b-c",
+            "-(a+1)",
+            "-a/2",
+        ] {
+            assert_eq!(parse_str(src).unwrap().to_string(), src);
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        match parse_str("SCREEN+") {
+            Err(ParseError::InvalidExpression(e, span)) => {
+                assert_eq!(e, "SCREEN+");
+                assert_eq!(span, Span::new(1, 8, 0));
+            }
+            r => panic!("expected invalid expression, got {r:?}"),
+        }
+
+        assert!(matches!(
+            parse_str("(1+2"),
+            Err(ParseError::InvalidExpression(..))
+        ));
+        assert!(matches!(
+            parse_str("1 2"),
+            Err(ParseError::InvalidExpression(..))
+        ));
+        assert!(matches!(
+            parse_str("0xZZ+1"),
+            Err(ParseError::InvalidNumber(..))
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::io::BufRead;
+
+#[derive(Debug, Clone)]
+pub enum HackError {
+    InvalidWord(String, u32),
+    Io(std::io::ErrorKind),
+}
+
+impl fmt::Display for HackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HackError::InvalidWord(word, line) => {
+                write!(f, "line {line}: `{word}` is not a 16 digit binary word")
+            }
+            HackError::Io(kind) => write!(f, "could not read input: {kind}"),
+        }
+    }
+}
+
+//reads .hack text, one 16 character binary word per line
+//blank lines are skipped, line numbers in errors start at 1
+pub fn read_words<R: BufRead>(reader: R) -> Result<Vec<u16>, HackError> {
+    let mut words = Vec::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| HackError::Io(e.kind()))?;
+        let word = line.trim();
+
+        if word.is_empty() {
+            continue;
+        }
+
+        if word.len() != 16 || !word.chars().all(|c| c == '0' || c == '1') {
+            return Err(HackError::InvalidWord(word.into(), i as u32 + 1));
+        }
+
+        //can't fail, checked above that it's 16 binary digits
+        words.push(u16::from_str_radix(word, 2).unwrap());
+    }
+
+    Ok(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_valid_words() {
+        let text = "0000000000000010\n\n1110110000010000\n";
+        let words = read_words(text.as_bytes()).unwrap();
+
+        assert_eq!(words, vec![2, 0b1110110000010000]);
+    }
+
+    #[test]
+    fn read_invalid_word() {
+        let text = "0000000000000010\n00000002\n";
+
+        match read_words(text.as_bytes()) {
+            Err(HackError::InvalidWord(w, line)) => {
+                assert_eq!(w, "00000002");
+                assert_eq!(line, 2);
+            }
+            _ => panic!("expected invalid word"),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::Options;
+use crate::cond::Conditions;
+use crate::lexer::{self, Token, TokenKind};
+use crate::parser::ParseError;
+use crate::source::{SourceFile, Sources};
+use crate::span::Span;
+
+struct Loader<'a> {
+    sources: &'a mut Sources,
+    dirs: &'a [PathBuf],
+    //conditions are followed while reading, so an .include in a skipped branch isn't read
+    conditions: Conditions,
+    //the files being read, the innermost last, so a file that includes itself is caught
+    open: Vec<PathBuf>,
+    lines: Vec<Vec<Token>>,
+    errors: Vec<ParseError>,
+}
+
+//tokenizes the main source, replacing every `.include "file.asm"` with the lines of the file
+//and dropping the lines .if and friends skip
+//an include is looked for next to the file it's in, then in each of the include dirs in order
+//also gives the end of the main source
+pub fn load(sources: &mut Sources, options: &Options) -> (Vec<Vec<Token>>, Vec<ParseError>, Span) {
+    let open = sources
+        .main()
+        .path
+        .iter()
+        .map(|p| p.canonicalize().unwrap_or_else(|_| p.clone()))
+        .collect();
+
+    let mut l = Loader {
+        sources,
+        dirs: &options.include_dirs,
+        conditions: Conditions::new(&options.predefined),
+        open,
+        lines: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let end = l.read(0);
+    l.errors.extend(l.conditions.finish());
+    (l.lines, l.errors, end)
+}
+
+impl Loader<'_> {
+    fn read(&mut self, file: u32) -> Span {
+        let tokens = lexer::tokenize_file(self.sources.text(file), file);
+        self.errors
+            .extend(tokens.errors.into_iter().map(ParseError::from));
+
+        for line in tokens.lines {
+            if !self.conditions.keep(&line, &mut self.errors) {
+                continue;
+            }
+
+            if line[0].kind == TokenKind::Word && line[0].text == ".include" {
+                self.include(&line, file);
+            } else {
+                self.lines.push(line);
+            }
+        }
+
+        tokens.end
+    }
+
+    fn include(&mut self, line: &[Token], from: u32) {
+        let span = lexer::span_of(line).unwrap();
+
+        let path = match line {
+            [_, path] if path.kind == TokenKind::Str => path,
+            _ => {
+                self.errors
+                    .push(ParseError::InvalidDirective(line[0].text.clone(), span));
+                return;
+            }
+        };
+
+        let including = self.sources.get(from).and_then(|f| f.path.as_deref());
+        let Some(found) = find(&path.text, including, self.dirs) else {
+            self.errors.push(ParseError::IncludeFailed(
+                path.text.clone(),
+                ErrorKind::NotFound,
+                path.span,
+            ));
+            return;
+        };
+
+        let canonical = found.canonicalize().unwrap_or_else(|_| found.clone());
+        if self.open.contains(&canonical) {
+            self.errors
+                .push(ParseError::RecursiveInclude(path.text.clone(), path.span));
+            return;
+        }
+
+        let text = match fs::read(&found) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                self.errors.push(ParseError::IncludeFailed(
+                    path.text.clone(),
+                    e.kind(),
+                    path.span,
+                ));
+                return;
+            }
+        };
+
+        let file = self.sources.add(SourceFile {
+            name: found.display().to_string(),
+            text,
+            path: Some(found),
+            included_from: Some(span),
+        });
+
+        self.open.push(canonical);
+        self.read(file);
+        self.open.pop();
+    }
+}
+
+//the first place the file is found, next to the including file (or in the current directory
+//for a source that isn't a file) and then in each of the include directories
+fn find(path: &str, including: Option<&Path>, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let here = including.and_then(Path::parent).unwrap_or(Path::new(""));
+
+    std::iter::once(here)
+        .chain(dirs.iter().map(PathBuf::as_path))
+        .map(|dir| dir.join(path))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //a fresh directory under the system temp dir holding the given files
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hacky_include_{test}"));
+        let _ = fs::remove_dir_all(&dir);
+
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        dir
+    }
+
+    fn load_main(dir: &Path, dirs: &[PathBuf]) -> (Sources, Vec<String>, Vec<ParseError>) {
+        let main = dir.join("main.asm");
+        let text = fs::read_to_string(&main).unwrap();
+        let mut sources = Sources::new("main.asm", text, Some(main));
+        let options = Options {
+            include_dirs: dirs.to_vec(),
+            ..Options::default()
+        };
+
+        let (lines, errors, _) = load(&mut sources, &options);
+        let texts = lines.iter().map(|l| lexer::text_of(l)).collect();
+
+        (sources, texts, errors)
+    }
+
+    #[test]
+    fn nested_and_search_paths() {
+        let dir = write_files(
+            "nested",
+            &[
+                (
+                    "main.asm",
+                    "@1\n.include \"sub/a.asm\"\n.include \"lib.asm\"\n@4\n",
+                ),
+                ("sub/a.asm", "@2\n.include \"b.asm\"\n"),
+                ("sub/b.asm", "@3\n"),
+                ("libs/lib.asm", "(MULT)\n"),
+            ],
+        );
+        let (sources, texts, errors) = load_main(&dir, &[dir.join("libs")]);
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(texts, vec!["@1", "@2", "@3", "(MULT)", "@4"]);
+
+        //b.asm was found next to a.asm, which was included from line 2 of main
+        assert_eq!(sources.include_chain(2).len(), 2);
+        assert_eq!(sources.in_main(Span::new(1, 1, 1).in_file(2)).line, 2);
+    }
+
+    #[test]
+    fn cycles_and_missing_files() {
+        let dir = write_files(
+            "cycles",
+            &[
+                (
+                    "main.asm",
+                    ".include \"a.asm\"\n.include \"missing.asm\"\n.include a.asm\n",
+                ),
+                ("a.asm", "@1\n.include \"main.asm\"\n"),
+            ],
+        );
+        let (sources, texts, errors) = load_main(&dir, &[]);
+
+        assert_eq!(texts, vec!["@1"]);
+        match errors.as_slice() {
+            [
+                ParseError::RecursiveInclude(r, span),
+                ParseError::IncludeFailed(m, ErrorKind::NotFound, _),
+                ParseError::InvalidDirective(d, _),
+            ] => {
+                assert_eq!(r, "main.asm");
+                assert_eq!(
+                    sources.name(span.file),
+                    dir.join("a.asm").display().to_string()
+                );
+                assert_eq!(m, "missing.asm");
+                assert_eq!(d, ".include");
+            }
+            r => panic!("expected include errors, got {r:?}"),
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::code::Code;
+
+//the enums below list their variants in the same order as the tables in code::Code,
+//so a variant's discriminant is its index into those tables
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dest {
+    Null,
+    M,
+    D,
+    DM,
+    A,
+    AM,
+    AD,
+    ADM,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comp {
+    Zero,
+    One,
+    NegOne,
+    D,
+    A,
+    NotD,
+    NotA,
+    NegD,
+    NegA,
+    DPlusOne,
+    APlusOne,
+    DMinusOne,
+    AMinusOne,
+    DPlusA,
+    DMinusA,
+    AMinusD,
+    DAndA,
+    DOrA,
+    M,
+    NotM,
+    NegM,
+    MPlusOne,
+    MMinusOne,
+    DPlusM,
+    DMinusM,
+    MMinusD,
+    DAndM,
+    DOrM,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Jump {
+    Null,
+    JGT,
+    JEQ,
+    JGE,
+    JLT,
+    JNE,
+    JLE,
+    JMP,
+}
+
+//the largest value an a-instruction can load, the top bit is what marks a c-instruction
+pub const MAX_A_VALUE: u16 = 0x7FFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    //15 bit value loaded into A
+    A(u16),
+    C { dest: Dest, comp: Comp, jump: Jump },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    //the top three bits of a c-instruction must be 111
+    InvalidPrefix(u16),
+    //the a and c bits don't match any computation
+    InvalidComp(u16),
+}
+
+//fills a table indexed by field bits from the Code bit strings
+fn bits_of(bits: &str) -> usize {
+    usize::from_str_radix(bits, 2).expect("code tables hold binary strings")
+}
+
+//comp bits (a + c1..c6) to comp, built once from the Code tables
+fn comp_table() -> &'static [Option<Comp>; 128] {
+    static TABLE: OnceLock<[Option<Comp>; 128]> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        let mut table = [None; 128];
+        for c in Comp::ALL {
+            table[bits_of(Code::comp(c.mnemonic()).unwrap())] = Some(c);
+        }
+        table
+    })
+}
+
+impl Dest {
+    pub const ALL: [Dest; 8] = [
+        Dest::Null,
+        Dest::M,
+        Dest::D,
+        Dest::DM,
+        Dest::A,
+        Dest::AM,
+        Dest::AD,
+        Dest::ADM,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        Code::dest_names()[self as usize]
+    }
+
+    //the registers can be given in any order, so MD is the same as DM
+    pub fn from_mnemonic(s: &str) -> Option<Dest> {
+        if s == "null" {
+            return Some(Dest::Null);
+        }
+
+        let mut chars: Vec<char> = s.chars().collect();
+        chars.sort();
+        let sorted: String = chars.into_iter().collect();
+
+        Self::ALL
+            .into_iter()
+            .find(|d| *d != Dest::Null && d.mnemonic() == sorted)
+    }
+
+    pub fn bits(self) -> u16 {
+        bits_of(Code::dest(self.mnemonic()).unwrap()) as u16
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Dest> {
+        Self::ALL.into_iter().find(|d| d.bits() == bits)
+    }
+
+    pub fn has_a(self) -> bool {
+        self.mnemonic().contains('A')
+    }
+
+    pub fn has_d(self) -> bool {
+        self.mnemonic().contains('D')
+    }
+
+    pub fn has_m(self) -> bool {
+        self.mnemonic().contains('M')
+    }
+}
+
+impl Comp {
+    pub const ALL: [Comp; 28] = [
+        Comp::Zero,
+        Comp::One,
+        Comp::NegOne,
+        Comp::D,
+        Comp::A,
+        Comp::NotD,
+        Comp::NotA,
+        Comp::NegD,
+        Comp::NegA,
+        Comp::DPlusOne,
+        Comp::APlusOne,
+        Comp::DMinusOne,
+        Comp::AMinusOne,
+        Comp::DPlusA,
+        Comp::DMinusA,
+        Comp::AMinusD,
+        Comp::DAndA,
+        Comp::DOrA,
+        Comp::M,
+        Comp::NotM,
+        Comp::NegM,
+        Comp::MPlusOne,
+        Comp::MMinusOne,
+        Comp::DPlusM,
+        Comp::DMinusM,
+        Comp::MMinusD,
+        Comp::DAndM,
+        Comp::DOrM,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        Code::comp_names()[self as usize]
+    }
+
+    pub fn from_mnemonic(s: &str) -> Option<Comp> {
+        Self::ALL.into_iter().find(|c| c.mnemonic() == s)
+    }
+
+    //the 7 bits a + c1..c6
+    pub fn bits(self) -> u16 {
+        bits_of(Code::comp(self.mnemonic()).unwrap()) as u16
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Comp> {
+        comp_table().get(bits as usize).copied().flatten()
+    }
+
+    //true when the computation reads memory rather than the A register
+    pub fn uses_m(self) -> bool {
+        self.mnemonic().contains('M')
+    }
+}
+
+impl Jump {
+    pub const ALL: [Jump; 8] = [
+        Jump::Null,
+        Jump::JGT,
+        Jump::JEQ,
+        Jump::JGE,
+        Jump::JLT,
+        Jump::JNE,
+        Jump::JLE,
+        Jump::JMP,
+    ];
+
+    pub fn mnemonic(self) -> &'static str {
+        Code::jump_names()[self as usize]
+    }
+
+    pub fn from_mnemonic(s: &str) -> Option<Jump> {
+        Self::ALL.into_iter().find(|j| j.mnemonic() == s)
+    }
+
+    pub fn bits(self) -> u16 {
+        bits_of(Code::jump(self.mnemonic()).unwrap()) as u16
+    }
+
+    pub fn from_bits(bits: u16) -> Option<Jump> {
+        Self::ALL.into_iter().find(|j| j.bits() == bits)
+    }
+
+    //whether the jump is taken for an alu output
+    pub fn taken(self, out: i16) -> bool {
+        match self {
+            Jump::Null => false,
+            Jump::JGT => out > 0,
+            Jump::JEQ => out == 0,
+            Jump::JGE => out >= 0,
+            Jump::JLT => out < 0,
+            Jump::JNE => out != 0,
+            Jump::JLE => out <= 0,
+            Jump::JMP => true,
+        }
+    }
+}
+
+impl Instruction {
+    //none for an a-instruction whose value needs more than 15 bits, its top bit would turn it
+    //into a c-instruction
+    pub fn encode(self) -> Option<u16> {
+        match self {
+            Instruction::A(v) => (v <= MAX_A_VALUE).then_some(v),
+
+            Instruction::C { dest, comp, jump } => {
+                Some(0xE000 | comp.bits() << 6 | dest.bits() << 3 | jump.bits())
+            }
+        }
+    }
+
+    pub fn decode(word: u16) -> Result<Instruction, DecodeError> {
+        if word & 0x8000 == 0 {
+            return Ok(Instruction::A(word));
+        }
+
+        if word & 0xE000 != 0xE000 {
+            return Err(DecodeError::InvalidPrefix(word));
+        }
+
+        let comp = Comp::from_bits((word >> 6) & 0x7F).ok_or(DecodeError::InvalidComp(word))?;
+        //every 3 bit value is a valid dest and jump
+        let dest = Dest::from_bits((word >> 3) & 0x7).unwrap();
+        let jump = Jump::from_bits(word & 0x7).unwrap();
+
+        Ok(Instruction::C { dest, comp, jump })
+    }
+}
+
+impl fmt::Display for Dest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl fmt::Display for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+impl fmt::Display for Jump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+//formats as hack assembly, leaving out null dest and jump parts
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::A(v) => write!(f, "@{v}"),
+
+            Instruction::C { dest, comp, jump } => {
+                if *dest != Dest::Null {
+                    write!(f, "{dest}=")?;
+                }
+
+                write!(f, "{comp}")?;
+
+                if *jump != Jump::Null {
+                    write!(f, ";{jump}")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Dest {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Dest::from_mnemonic(s).ok_or(())
+    }
+}
+
+impl FromStr for Comp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Comp::from_mnemonic(s).ok_or(())
+    }
+}
+
+impl FromStr for Jump {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Jump::from_mnemonic(s).ok_or(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_matches_code_tables() {
+        let i = Instruction::C {
+            dest: Dest::M,
+            comp: Comp::DPlusM,
+            jump: Jump::Null,
+        };
+
+        let bits = format!(
+            "111{}{}{}",
+            Code::comp("D+M").unwrap(),
+            Code::dest("M").unwrap(),
+            Code::jump("null").unwrap()
+        );
+
+        assert_eq!(format!("{:016b}", i.encode().unwrap()), bits);
+        assert_eq!(Instruction::A(21).encode(), Some(21));
+        assert_eq!(Instruction::A(0x8000).encode(), None);
+    }
+
+    #[test]
+    fn decode_round_trips_every_instruction() {
+        for comp in Comp::ALL {
+            for dest in Dest::ALL {
+                for jump in Jump::ALL {
+                    let i = Instruction::C { dest, comp, jump };
+                    assert_eq!(Instruction::decode(i.encode().unwrap()), Ok(i));
+                }
+            }
+        }
+
+        assert_eq!(Instruction::decode(0x7FFF), Ok(Instruction::A(0x7FFF)));
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(
+            Instruction::decode(0b1010101010000111),
+            Err(DecodeError::InvalidPrefix(0b1010101010000111))
+        );
+        assert_eq!(
+            Instruction::decode(0b1111111111000000),
+            Err(DecodeError::InvalidComp(0b1111111111000000))
+        );
+    }
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(Dest::from_mnemonic("MD"), Some(Dest::DM));
+        assert_eq!(Dest::from_mnemonic("MM"), None);
+        assert_eq!("D|M".parse::<Comp>(), Ok(Comp::DOrM));
+        assert_eq!("JLE".parse::<Jump>(), Ok(Jump::JLE));
+
+        let i = Instruction::C {
+            dest: Dest::AM,
+            comp: Comp::MMinusOne,
+            jump: Jump::JNE,
+        };
+        assert_eq!(i.to_string(), "AM=M-1;JNE");
+    }
+}
@@ -0,0 +1,374 @@
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    //a run of symbol characters, covers names, numbers and mnemonics like JMP or AM
+    Word,
+    //any other single character, e.g. @ ( ) = ; + - ! & |
+    Punct(char),
+    //text in double quotes, the token text is what's between them
+    Str,
+    //a character in single quotes like 'A', the token text is the character
+    Char,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl Token {
+    pub fn is_punct(&self, c: char) -> bool {
+        self.kind == TokenKind::Punct(c)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    //span of the opening /*
+    UnterminatedComment(Span),
+    //span of the string up to the end of its line
+    UnterminatedString(Span),
+}
+
+//chars that can appear in a symbol, per the hack spec
+pub fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$' | ':')
+}
+
+//a name that can be used for a label or variable, symbol chars that don't start with a digit
+pub fn is_symbol(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit()) && name.chars().all(is_word_char)
+}
+
+//the value of a decimal, 0x hex or 0b binary number, none if the text isn't one
+//values too large for a u32 are u32::MAX, they are out of range either way
+pub fn parse_number(text: &str) -> Option<u32> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if let Some(bin) = text.strip_prefix("0b").or(text.strip_prefix("0B")) {
+        (bin, 2)
+    } else {
+        (text, 10)
+    };
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.chars().try_fold(0u32, |n, c| {
+        let d = c.to_digit(radix)?;
+        Some(n.saturating_mul(radix).saturating_add(d))
+    })
+}
+
+//a 'c' or a word starting with a digit, which is a number or a mistake but never a name
+pub fn is_number(token: &Token) -> bool {
+    match token.kind {
+        TokenKind::Char => true,
+        TokenKind::Word => token.text.starts_with(|c: char| c.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+//the value of a number token, a char is its unicode code point
+pub fn number_value(token: &Token) -> Option<u32> {
+    match token.kind {
+        TokenKind::Char => token.text.chars().next().map(u32::from),
+        _ => parse_number(&token.text),
+    }
+}
+
+//the tokens found in a source file, grouped by the line they are on
+//comments, blank lines and whitespace are dropped so every line holds at least one token
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    pub lines: Vec<Vec<Token>>,
+    pub errors: Vec<LexError>,
+    //position just past the end of the source
+    pub end: Span,
+}
+
+pub fn tokenize(source: &str) -> Tokens {
+    tokenize_file(source, 0)
+}
+
+//tokenizes a source that was included, every span is given the file's index
+pub fn tokenize_file(source: &str, file: u32) -> Tokens {
+    let at = |line, col, len| Span::new(line, col, len).in_file(file);
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+
+    let mut out = Tokens::default();
+    let mut current: Vec<Token> = Vec::new();
+
+    let mut chars = source.chars().peekable();
+    let mut line: u32 = 1;
+    let mut col: u32 = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                if !current.is_empty() {
+                    out.lines.push(std::mem::take(&mut current));
+                }
+                line += 1;
+                col = 1;
+                continue;
+            }
+
+            //covers the \r of a crlf ending as well as spaces and tabs
+            c if c.is_whitespace() => {}
+
+            '/' if chars.peek() == Some(&'/') => {
+                //line comment, skip up to the newline and let the loop handle it
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+
+            '/' if chars.peek() == Some(&'*') => {
+                let start = at(line, col, 2);
+                chars.next();
+                col += 2;
+
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        col += 2;
+                        closed = true;
+                        break;
+                    }
+
+                    if c == '\n' {
+                        line += 1;
+                        col = 1;
+                    } else {
+                        col += 1;
+                    }
+                }
+
+                if !closed {
+                    out.errors.push(LexError::UnterminatedComment(start));
+                }
+                continue;
+            }
+
+            c if is_word_char(c) => {
+                let mut text = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if !is_word_char(next) {
+                        break;
+                    }
+                    text.push(next);
+                    chars.next();
+                }
+
+                let len = text.chars().count() as u32;
+                current.push(Token {
+                    kind: TokenKind::Word,
+                    text,
+                    span: at(line, col, len),
+                });
+                col += len;
+                continue;
+            }
+
+            '"' => {
+                let mut text = String::new();
+                let mut closed = false;
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' {
+                        break;
+                    }
+                    chars.next();
+                    if next == '"' {
+                        closed = true;
+                        break;
+                    }
+                    text.push(next);
+                }
+
+                //the quotes are part of the span but not the text
+                let len = text.chars().count() as u32 + 1 + closed as u32;
+                let span = at(line, col, len);
+                if closed {
+                    current.push(Token {
+                        kind: TokenKind::Str,
+                        text,
+                        span,
+                    });
+                } else {
+                    out.errors.push(LexError::UnterminatedString(span));
+                }
+                col += len;
+                continue;
+            }
+
+            '\'' if closes_char(chars.clone()) => {
+                let c = chars.next().unwrap();
+                chars.next();
+
+                current.push(Token {
+                    kind: TokenKind::Char,
+                    text: c.into(),
+                    span: at(line, col, 3),
+                });
+                col += 3;
+                continue;
+            }
+
+            c => current.push(Token {
+                kind: TokenKind::Punct(c),
+                text: c.into(),
+                span: at(line, col, 1),
+            }),
+        }
+
+        col += 1;
+    }
+
+    if !current.is_empty() {
+        out.lines.push(current);
+    }
+
+    out.end = at(line, col, 0);
+    out
+}
+
+//whether the chars after a ' are a character and the closing '
+fn closes_char(mut ahead: impl Iterator<Item = char>) -> bool {
+    matches!((ahead.next(), ahead.next()), (Some(c), Some('\'')) if c != '\n')
+}
+
+//span covering every token in the slice
+//tokens can only be on different lines if a block comment splits them, then just the first is used
+pub fn span_of(tokens: &[Token]) -> Option<Span> {
+    let first = tokens.first()?.span;
+    let last = tokens.last()?.span;
+
+    if first.line != last.line {
+        return Some(first);
+    }
+
+    Some(Span {
+        len: last.end_col() - first.col,
+        ..first
+    })
+}
+
+//the tokens joined back together without whitespace
+pub fn text_of(tokens: &[Token]) -> String {
+    tokens.iter().map(|t| t.text.as_str()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn texts(tokens: &Tokens) -> Vec<Vec<&str>> {
+        tokens
+            .lines
+            .iter()
+            .map(|l| l.iter().map(|t| t.text.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn trailing_and_block_comments() {
+        let src = "D=M // load\n  /* a\n block */ @i\n/* whole line */\n0;JMP";
+        let tokens = tokenize(src);
+
+        assert_eq!(
+            texts(&tokens),
+            vec![vec!["D", "=", "M"], vec!["@", "i"], vec!["0", ";", "JMP"]]
+        );
+        assert!(tokens.errors.is_empty());
+
+        //@ on line 3, after the end of the block comment
+        assert_eq!(tokens.lines[1][0].span, Span::new(3, 11, 1));
+    }
+
+    #[test]
+    fn crlf_and_bom() {
+        let src = "\u{feff}@2\r\n\tD=A\r\n";
+        let tokens = tokenize(src);
+
+        assert_eq!(texts(&tokens), vec![vec!["@", "2"], vec!["D", "=", "A"]]);
+        assert_eq!(tokens.lines[0][0].span, Span::new(1, 1, 1));
+        assert_eq!(tokens.lines[1][2].span, Span::new(2, 4, 1));
+    }
+
+    #[test]
+    fn symbol_chars() {
+        let tokens = tokenize("@LOOP_math.multiply$ret:1");
+
+        assert_eq!(texts(&tokens), vec![vec!["@", "LOOP_math.multiply$ret:1"]]);
+    }
+
+    #[test]
+    fn symbol_names() {
+        assert!(is_symbol("math.multiply$ret:1"));
+        assert!(!is_symbol("1st"));
+        assert!(!is_symbol("has space"));
+        assert!(!is_symbol(""));
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let tokens = tokenize("@1\n  /* never closed\n@2");
+
+        assert_eq!(texts(&tokens), vec![vec!["@", "1"]]);
+        assert_eq!(
+            tokens.errors,
+            vec![LexError::UnterminatedComment(Span::new(2, 3, 2))]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let tokens = tokenize(".include \"lib/mult.asm\"\n.include \"open\n");
+
+        assert_eq!(
+            texts(&tokens),
+            vec![vec![".include", "lib/mult.asm"], vec![".include"]]
+        );
+        assert_eq!(tokens.lines[0][1].kind, TokenKind::Str);
+        assert_eq!(tokens.lines[0][1].span, Span::new(1, 10, 14));
+        assert_eq!(
+            tokens.errors,
+            vec![LexError::UnterminatedString(Span::new(2, 10, 5))]
+        );
+    }
+
+    #[test]
+    fn file_index() {
+        let tokens = tokenize_file("@1\n/*", 2);
+
+        assert_eq!(tokens.lines[0][1].span, Span::new(1, 2, 1).in_file(2));
+        assert_eq!(
+            tokens.errors[0],
+            LexError::UnterminatedComment(Span::new(2, 1, 2).in_file(2))
+        );
+    }
+
+    #[test]
+    fn numbers_and_chars() {
+        assert_eq!(parse_number("0x4000"), Some(16384));
+        assert_eq!(parse_number("0B101"), Some(5));
+        assert_eq!(parse_number("32767"), Some(32767));
+        assert_eq!(parse_number("99999999999"), Some(u32::MAX));
+        assert_eq!(parse_number("0x"), None);
+        assert_eq!(parse_number("12ab"), None);
+
+        let tokens = tokenize("@'A'+' '\n'");
+        assert_eq!(texts(&tokens), vec![vec!["@", "A", "+", " "], vec!["'"]]);
+        assert_eq!(tokens.lines[0][1].kind, TokenKind::Char);
+        assert_eq!(tokens.lines[0][3].span, Span::new(1, 6, 3));
+        assert_eq!(number_value(&tokens.lines[0][1]), Some(65));
+        assert!(is_number(&tokens.lines[0][3]));
+        assert!(!is_number(&tokens.lines[0][0]));
+    }
+}
@@ -1,102 +1,786 @@
-use std::collections::HashMap;
-
+pub mod builder;
 pub mod code;
+pub mod codegen;
+pub mod cond;
+pub mod data;
+pub mod diagnostic;
+pub mod disasm;
+pub mod emulator;
+pub mod expr;
+pub mod hack;
+pub mod include;
+pub mod instruction;
+pub mod lexer;
+pub mod listing;
+pub mod macros;
+pub mod output;
 pub mod parser;
+pub mod pseudo;
+pub mod source;
+pub mod span;
 pub mod symbol;
 
-use crate::code::Code;
-use crate::parser::{Command, ParseError, Parser, Symbol};
+use crate::instruction::{Dest, Instruction, Jump, MAX_A_VALUE};
+use crate::listing::Entry;
+use crate::output::{OutputFormat, SymbolFormat};
+use crate::parser::{Command, NumberUse, ParseError, Parser, Statement, Symbol};
+use crate::source::Sources;
+use crate::span::Span;
+use crate::symbol::{SymbolError, SymbolMode, SymbolTable, SymbolWarning};
 use std::fs::File;
-use std::io::{BufRead, Seek, Write};
-use std::path::PathBuf;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub enum AsmError {
+    Parse(ParseError),
+    Symbol(SymbolError),
+    //reading the source failed, nothing was assembled
+    Read(std::io::ErrorKind),
+    //writing the output failed
+    Io(std::io::ErrorKind),
+    //the number of words in the program and the rom size
+    ProgramTooLarge(usize, usize),
+}
+
+impl AsmError {
+    //errors with no place in the source, like io errors, give an empty span on line 0
+    pub fn span(&self) -> Span {
+        match self {
+            AsmError::Parse(e) => e.span(),
+            AsmError::Symbol(e) => e.span(),
+            AsmError::Read(_) | AsmError::Io(_) | AsmError::ProgramTooLarge(..) => Span::default(),
+        }
+    }
+}
+
+impl From<ParseError> for AsmError {
+    fn from(e: ParseError) -> Self {
+        AsmError::Parse(e)
+    }
+}
+
+impl From<SymbolError> for AsmError {
+    fn from(e: SymbolError) -> Self {
+        AsmError::Symbol(e)
+    }
+}
 
-pub struct Assembler<R: BufRead + Seek> {
-    symbols: HashMap<String, String>,
-    parser: Parser<R>,
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub symbol_mode: SymbolMode,
+    //symbols defined before the source is read, the hack platform's by default
+    //they can also be used in .if conditions, which is how -D definitions are passed in
+    pub predefined: Vec<(String, u16)>,
+    //first ram address given to variables
+    pub variable_base: u16,
+    //last ram address variables can use, the word below the screen by default
+    pub variable_limit: u16,
+    //words in the rom the program is loaded into, the hardware images are padded to this
+    pub rom_size: usize,
+    //searched in order for a .include that isn't next to the file including it
+    pub include_dirs: Vec<PathBuf>,
 }
 
-impl<R: BufRead + Seek> Assembler<R> {
-    pub fn new(mut reader: R) -> Result<Self, symbol::SymbolError> {
-        let symbols = symbol::create_table(&mut reader)?;
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            symbol_mode: SymbolMode::default(),
+            predefined: symbol::hack_predefined(),
+            variable_base: 16,
+            variable_limit: symbol::MAX_VARIABLE_ADDR,
+            rom_size: emulator::ROM_SIZE,
+            include_dirs: Vec::new(),
+        }
+    }
+}
+
+pub struct Assembler {
+    //the main source and everything it included, kept for the listing and diagnostics
+    sources: Sources,
+    statements: Vec<Statement>,
+    symbols: SymbolTable,
+    //everything wrong with the source, found up front by parsing and building the symbol table
+    errors: Vec<AsmError>,
+    //things that assemble but are probably mistakes, they don't stop the output being written
+    warnings: Vec<SymbolWarning>,
+    rom_size: usize,
+}
+
+impl Assembler {
+    //parses the source once, the symbol table and the output are both built from the parsed statements
+    pub fn new<R: BufRead>(reader: R) -> Self {
+        Self::with_options(reader, Options::default())
+    }
 
-        Ok(Assembler {
+    //includes in the source are looked for in the current directory
+    //a reader that fails gives an assembler holding just that error
+    pub fn with_options<R: BufRead>(reader: R, options: Options) -> Self {
+        match parser::read_source(reader) {
+            Ok(source) => Self::from_sources(Sources::new("", source, None), options),
+            Err(e) => {
+                let errors = vec![AsmError::Read(e.kind())];
+                Self::build(Sources::new("", "", None), Vec::new(), errors, options)
+            }
+        }
+    }
+
+    //includes are looked for next to the file, and it's named by its path in diagnostics
+    pub fn from_file(path: &Path, options: Options) -> std::io::Result<Self> {
+        let source = parser::read_source(std::io::BufReader::new(File::open(path)?))?;
+        let sources = Sources::new(path.display().to_string(), source, Some(path.into()));
+
+        Ok(Self::from_sources(sources, options))
+    }
+
+    fn from_sources(mut sources: Sources, options: Options) -> Self {
+        let (statements, parse_errors) =
+            Parser::from_sources(&mut sources, &options).parse_all();
+        let errors = parse_errors.into_iter().map(AsmError::from).collect();
+
+        Self::build(sources, statements, errors, options)
+    }
+
+    //assembles statements that were made in code rather than parsed, see builder::ProgramBuilder
+    //the spans should point into the source the statements print as, one per line
+    pub fn from_statements(statements: Vec<Statement>, options: Options) -> Self {
+        let source: String = statements
+            .iter()
+            .map(|s| format!("{}\n", s.command))
+            .collect();
+        let errors = check_statements(&statements);
+
+        Self::build(Sources::new("", source, None), statements, errors, options)
+    }
+
+    fn build(
+        sources: Sources,
+        statements: Vec<Statement>,
+        mut errors: Vec<AsmError>,
+        options: Options,
+    ) -> Self {
+        //checked before anything is encoded, so a huge program is reported rather than
+        //overflowing an address
+        let words = statements
+            .iter()
+            .filter(|s| s.command.is_instruction())
+            .count();
+        if words > options.rom_size {
+            errors.push(AsmError::ProgramTooLarge(words, options.rom_size));
+        }
+
+        let (symbols, symbol_errors) = match symbol::create_table(&statements, &options) {
+            Ok(t) => (t, Vec::new()),
+            Err(e) => (SymbolTable::new(), e),
+        };
+        let warnings = symbol::labels_used_as_data(&statements, &symbols);
+
+        errors.extend(symbol_errors.into_iter().map(AsmError::from));
+        //errors in an included file go where it was included
+        errors.sort_by_key(|e| {
+            let s = e.span();
+            let m = sources.in_main(s);
+            (m.line, m.col, s.file, s.line, s.col)
+        });
+
+        Assembler {
+            sources,
+            statements,
             symbols,
-            parser: Parser::new(reader),
-        })
-    }
-
-    pub fn assemble(&mut self, out: PathBuf) -> Result<(), ParseError> {
-        let mut file = File::create(out).expect("failed to create output file");
-
-        let mut output_lines = Vec::new(); 
-
-        while self.parser.has_more_commands() {
-            self.parser.advance()?;
-
-            if let Some(s) = self.parser.get_current() {
-                match s {
-                    Command::ACommand { symbol } => match symbol {
-                        Symbol::Decimal(d) => {
-                            output_lines.push(format!("{:016b}", d));
-                        }
-                        Symbol::Name(n) => {
-                            if let Some(s) = self.symbols.get(n) {
-                                output_lines.push(s.clone());
-                            }
-                        }
-                    },
-
-                    Command::CCommand { dest, comp, jmp } => {
-                        let d = dest
-                            .as_ref()
-                            .map(|d| Code::dest(d).unwrap())
-                            .unwrap_or("000");
-                        let c = Code::comp(&comp).unwrap();
-                        let j = jmp
-                            .as_ref()
-                            .map(|j| Code::jump(j).unwrap())
-                            .unwrap_or("000");
-
-                        output_lines.push(format!("111{c}{d}{j}"));
-                    }
+            errors,
+            warnings,
+            rom_size: options.rom_size,
+        }
+    }
+
+    pub fn sources(&self) -> &Sources {
+        &self.sources
+    }
+
+    pub fn statements(&self) -> &[Statement] {
+        &self.statements
+    }
+
+    //empty when the symbol table could not be built
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    pub fn warnings(&self) -> &[SymbolWarning] {
+        &self.warnings
+    }
 
-                    Command::LCommand { .. } => {
-                        //labels are ignored
+    //the statements lowered to machine instructions, labels produce nothing
+    //every name should be in the table by now, a missing one is reported rather than dropped
+    //so the output can never silently lose an instruction
+    fn instructions(&self) -> Result<Vec<Instruction>, Vec<AsmError>> {
+        let mut instructions = Vec::new();
+        let mut errors = Vec::new();
+
+        for s in self.statements.iter() {
+            match &s.command {
+                Command::ACommand { symbol } => {
+                    let value = match symbol {
+                        Symbol::Decimal(d) => Ok(*d),
+                        Symbol::Name(n) => self
+                            .symbols
+                            .address(n)
+                            .ok_or_else(|| SymbolError::UnresolvedSymbol(n.clone(), s.name_span)),
+                        Symbol::Expr(e) => self.symbols.evaluate(e, s.name_span),
+                    };
+
+                    //checked again here so no value that needs 16 bits can reach the encoder
+                    match value {
+                        Ok(v) if v <= MAX_A_VALUE => instructions.push(Instruction::A(v)),
+                        Ok(v) => errors.push(SymbolError::OutOfRange(v.into(), s.name_span).into()),
+                        Err(e) => errors.push(e.into()),
                     }
                 }
+
+                Command::CCommand { dest, comp, jmp } => instructions.push(Instruction::C {
+                    dest: dest.unwrap_or(Dest::Null),
+                    comp: *comp,
+                    jump: jmp.unwrap_or(Jump::Null),
+                }),
+
+                Command::LCommand { .. }
+                | Command::Constant { .. }
+                | Command::Alias { .. }
+                | Command::Var { .. } => {
+                    //labels and definitions are only used for the symbol table
+                }
             }
         }
 
-        let output = output_lines.join("\n");
-        write!(file, "{}", output).unwrap();
+        if errors.is_empty() {
+            Ok(instructions)
+        } else {
+            Err(errors)
+        }
+    }
+
+    //a bad line is skipped so that every error is collected and returned together,
+    //sorted by where they are in the source
+    pub fn assemble_to_words(&self) -> Result<Vec<u16>, Vec<AsmError>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors.clone());
+        }
+
+        let instructions = self.instructions()?;
+        Ok(instructions
+            .iter()
+            .map(|i| i.encode().expect("a-instruction values are checked when built"))
+            .collect())
+    }
+
+    pub fn assemble_to<W: Write>(
+        &self,
+        w: &mut W,
+        format: OutputFormat,
+    ) -> Result<(), Vec<AsmError>> {
+        let words = self.assemble_to_words()?;
+
+        output::write_program(w, &words, &self.symbols, format, self.rom_size)
+            .map_err(|e| vec![AsmError::Io(e.kind())])
+    }
+
+    //the resolved symbols, for mapping addresses back to names when debugging
+    pub fn write_symbols<W: Write>(
+        &self,
+        w: &mut W,
+        format: SymbolFormat,
+    ) -> Result<(), Vec<AsmError>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors.clone());
+        }
 
-        Ok(())
+        output::write_symbols(w, &self.symbols, &self.sources, format)
+            .map_err(|e| vec![AsmError::Io(e.kind())])
+    }
+
+    //each source line beside its rom address, the encoded word and the value of any @symbol
+    pub fn write_listing<W: Write>(&self, w: &mut W) -> Result<(), Vec<AsmError>> {
+        let words = self.assemble_to_words()?;
+        let mut words = words.into_iter();
+
+        let mut entries = Vec::new();
+        let mut address: u16 = 0;
+
+        for s in self.statements.iter() {
+            let (word, value) = match &s.command {
+                Command::LCommand { .. } => (None, None),
+
+                Command::Constant { name, .. }
+                | Command::Alias { name, .. }
+                | Command::Var { name, .. } => (None, self.symbols.address(name)),
+
+                Command::ACommand {
+                    symbol: Symbol::Name(n),
+                } => (words.next(), self.symbols.address(n)),
+
+                Command::ACommand {
+                    symbol: Symbol::Expr(e),
+                } => (words.next(), self.symbols.evaluate(e, s.name_span).ok()),
+
+                Command::ACommand { .. } | Command::CCommand { .. } => (words.next(), None),
+            };
+
+            //code from a macro or an included file is listed under the call or .include
+            let site = s.span.call_site.map_or(s.span, |c| c.span());
+            let (line, expanded) = if s.span.call_site.is_some() || s.span.file != 0 {
+                (self.sources.in_main(site).line, Some(s.command.to_string()))
+            } else {
+                (s.span.line, None)
+            };
+
+            entries.push(Entry {
+                line,
+                address,
+                word,
+                value,
+                expanded,
+            });
+
+            if word.is_some() {
+                address += 1;
+            }
+        }
+
+        //startup code for .data runs first but is listed under its line further down
+        //a line's own statement goes before anything expanded under it
+        entries.sort_by_key(|e| (e.line, e.expanded.is_some()));
+
+        listing::write_listing(w, &self.sources.main().text, &entries)
+            .map_err(|e| vec![AsmError::Io(e.kind())])
+    }
+
+    //writes a .hack file, nothing is created if the source has errors
+    pub fn assemble(&self, out: PathBuf) -> Result<(), Vec<AsmError>> {
+        self.assemble_as(out, OutputFormat::Hack)
+    }
+
+    pub fn assemble_as(&self, out: PathBuf, format: OutputFormat) -> Result<(), Vec<AsmError>> {
+        let words = self.assemble_to_words()?;
+
+        let mut file = File::create(out).map_err(|e| vec![AsmError::Io(e.kind())])?;
+
+        output::write_program(&mut file, &words, &self.symbols, format, self.rom_size)
+            .map_err(|e| vec![AsmError::Io(e.kind())])
     }
 }
 
+//what the parser would have rejected, for statements that didn't come from it
+fn check_statements(statements: &[Statement]) -> Vec<AsmError> {
+    let mut errors = Vec::new();
+
+    for s in statements.iter() {
+        let name = match &s.command {
+            Command::ACommand {
+                symbol: Symbol::Decimal(d),
+            } if *d > 32767 => {
+                let e = ParseError::IntegerOverflow(d.to_string(), NumberUse::AInstruction, s.name_span);
+                errors.push(e.into());
+                continue;
+            }
+
+            Command::ACommand {
+                symbol: Symbol::Name(n),
+            } => n,
+            Command::LCommand { label } => label,
+            Command::Constant { name, .. } | Command::Var { name, .. } => name,
+            Command::Alias { name, target } => {
+                if !lexer::is_symbol(target) {
+                    errors.push(SymbolError::InvalidSymbol(target.clone(), s.span).into());
+                }
+                name
+            }
+            _ => continue,
+        };
+
+        if !lexer::is_symbol(name) {
+            errors.push(SymbolError::InvalidSymbol(name.clone(), s.name_span).into());
+        }
+    }
+
+    errors
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use crate::emulator::Machine;
     use std::fs;
     use std::io::BufReader;
 
-    #[test]
-    pub fn test_parse() {
+    fn read_pong() -> (Assembler, Vec<u16>) {
         let mut bp = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         bp.push("test");
         bp.push("Pong.asm");
 
-        let mut out = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        out.push("test");
-        out.push("Pong.hack");
+        let mut expected = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        expected.push("asm");
+        expected.push("Pong.hack");
 
         let file = fs::File::open(bp).expect("failed to open asm file");
+        let assembler = Assembler::new(BufReader::new(file));
 
-        let reader = BufReader::new(file);
+        let file = fs::File::open(expected).expect("failed to open hack file");
+        let words = hack::read_words(BufReader::new(file)).unwrap();
 
-        let mut assembler = Assembler::new(reader).unwrap();
+        (assembler, words)
+    }
+
+    #[test]
+    pub fn test_parse() {
+        let (assembler, expected) = read_pong();
+
+        assert_eq!(assembler.assemble_to_words().unwrap(), expected);
+    }
+
+    #[test]
+    pub fn test_assemble_file() {
+        let (assembler, _) = read_pong();
+
+        let out = std::env::temp_dir().join("hacky_test_assemble_file.hack");
+        assembler.assemble(out.clone()).unwrap();
+
+        let mut expected = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        expected.push("asm");
+        expected.push("Pong.hack");
+
+        assert_eq!(fs::read(&out).unwrap(), fs::read(expected).unwrap());
+        fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    pub fn test_assemble_to_writer() {
+        let assembler = Assembler::new("@2\nD=A\n".as_bytes());
+
+        let mut out = Vec::new();
+        assembler.assemble_to(&mut out, OutputFormat::Hack).unwrap();
+        assert_eq!(out, b"0000000000000010\n1110110000010000");
+
+        let mut out = Vec::new();
+        assembler.assemble_to(&mut out, OutputFormat::BinaryBe).unwrap();
+        assert_eq!(out, vec![0x00, 0x02, 0xEC, 0x10]);
+    }
+
+    #[test]
+    pub fn test_constants_and_macros() {
+        let src = ".equ ROW 32\n\
+                   .alias i R13\n\
+                   .macro SET reg, value\n\
+                   @value\n\
+                   D=A\n\
+                   @reg\n\
+                   M=D\n\
+                   .endm\n\
+                   SET i, ROW\n\
+                   SET x, 1\n";
+        let assembler = Assembler::new(src.as_bytes());
+
+        //constants and aliases take no rom, and x is still the first variable
+        let words = assembler.assemble_to_words().unwrap();
+        assert_eq!(
+            words,
+            vec![32, 0xEC10, 13, 0xE308, 1, 0xEC10, 16, 0xE308]
+        );
+
+        let mut listing = Vec::new();
+        assembler.write_listing(&mut listing).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+
+        //the expansion is listed under the call
+        assert!(listing.contains("SET i, ROW\n"));
+        assert!(listing.contains("    2  0000000000001101  000D     13    @i\n"));
+    }
+
+    #[test]
+    pub fn test_conditional_assembly() {
+        let src = ".ifdef DEBUG\n@DEBUG\nD=A\n.endif\n(END)\n@END\n0;JMP\n";
+
+        let release = Assembler::new(src.as_bytes()).assemble_to_words().unwrap();
+        assert_eq!(release, vec![0, 0xEA87]);
+
+        let mut options = Options::default();
+        options.predefined.push(("DEBUG".into(), 7));
+        let debug = Assembler::with_options(src.as_bytes(), options)
+            .assemble_to_words()
+            .unwrap();
+        //the label moves past the debug code
+        assert_eq!(debug, vec![7, 0xEC10, 2, 0xEA87]);
+
+        let mut options = Options::default();
+        options.predefined.push(("DEBUG".into(), 7));
+        let errors = Assembler::with_options(".equ DEBUG 1
+".as_bytes(), options)
+            .assemble_to_words()
+            .unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [AsmError::Symbol(SymbolError::RedefinedDefine(..))]
+        ));
+    }
+
+    #[test]
+    pub fn test_expressions() {
+        let src = ".equ ROW 32\n.var table 4\n@SCREEN+ROW*5\nD=A\n@table+3\nM=D\n@'A'\n(END)\n\
+                   @END-1\n0;JMP\n";
+        let words = Assembler::new(src.as_bytes()).assemble_to_words().unwrap();
+
+        assert_eq!(words, vec![16544, 0xEC10, 19, 0xE308, 65, 4, 0xEA87]);
+    }
+
+    #[test]
+    pub fn test_pseudo_instructions() {
+        let src = "(LOOP)\nD=[i]\nif D<0 goto END\n@-1\nD=A\n[i]=D\ngoto LOOP\n(END)\n";
+        let assembler = Assembler::new(src.as_bytes());
+
+        let words = assembler.assemble_to_words().unwrap();
+        assert_eq!(
+            words,
+            vec![
+                16, 0xFC10, 11, 0xE304, 1, 0xECE0, 0xEC10, 16, 0xE308, 0, 0xEA87
+            ]
+        );
+
+        //each expansion is listed under the pseudo-instruction
+        let mut out = Vec::new();
+        assembler.write_listing(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = out.lines().skip(15).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "                                       goto LOOP",
+                "    9  0000000000000000  0000      0    @LOOP",
+                "   10  1110101010000111  EA87           0;JMP",
+                "   11                                 (END)",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_ram_data() {
+        let src = ".var buf 4\n.var x @ 17\n.data table 3, -1\n.string s \"A\"\n\
+                   @buf\nM=0\n@i\nM=0\n(END)\n@END\n0;JMP\n";
+        let assembler = Assembler::new(src.as_bytes());
+
+        //buf doesn't fit between 16 and x at 17, so it goes after x
+        let symbols = assembler.symbols();
+        let addresses: Vec<u16> = ["buf", "x", "table", "s", "i"]
+            .iter()
+            .map(|n| symbols.address(n).unwrap())
+            .collect();
+        assert_eq!(addresses, vec![18, 17, 22, 24, 26]);
+
+        let mut machine = Machine::new();
+        machine.load(&assembler.assemble_to_words().unwrap()).unwrap();
+        machine.run(1000).unwrap();
+        assert_eq!(&machine.ram()[22..26], &[3, 0xFFFF, 65, 0]);
+
+        //the startup code is listed under the .data and .string lines
+        let mut out = Vec::new();
+        assembler.write_listing(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = out.lines().skip(3).take(3).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "   12                             22  .data table 3, -1",
+                "    0  0000000000000011  0003           @3",
+                "    1  1110110000010000  EC10           D=A",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_rom_size() {
+        let options = Options {
+            rom_size: 4,
+            ..Options::default()
+        };
+        let assembler = Assembler::with_options("@1\nD=A\n".as_bytes(), options.clone());
+
+        let mut out = Vec::new();
+        assembler.assemble_to(&mut out, OutputFormat::ReadMemH).unwrap();
+        assert_eq!(out, b"// hack rom, 2 words, 4 deep\n0001\nEC10\n0000\n0000\n");
+
+        let assembler = Assembler::with_options("@1\n@2\n@3\n@4\n@5\n".as_bytes(), options);
+        let errors = assembler.assemble_to_words().unwrap_err();
+        assert!(matches!(errors.as_slice(), [AsmError::ProgramTooLarge(5, 4)]));
+    }
+
+    #[test]
+    pub fn test_program_larger_than_addresses() {
+        //END is past 32767, where no a-instruction can reach
+        let src = format!("@END\n0;JMP\n{}(END)\n", "D=A\n".repeat(32770));
+        let errors = Assembler::new(src.as_bytes()).assemble_to_words().unwrap_err();
+
+        match errors.as_slice() {
+            [
+                AsmError::ProgramTooLarge(32772, 32768),
+                AsmError::Symbol(SymbolError::LabelOutOfRange(end, 32772, _)),
+            ] => assert_eq!(end, "END"),
+            r => panic!("expected a too large program, got {r:?}"),
+        }
+
+        //more instructions than a u16 can count
+        let src = "D=A\n".repeat(65537);
+        let errors = Assembler::new(src.as_bytes()).assemble_to_words().unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [AsmError::ProgramTooLarge(65537, 32768)]
+        ));
+    }
+
+    #[test]
+    pub fn test_predefined_too_big_for_a_instruction() {
+        let mut options = Options::default();
+        options.predefined.push(("BIG".into(), 40000));
+        let errors = Assembler::with_options("@BIG\n@BIG+1\n".as_bytes(), options)
+            .assemble_to_words()
+            .unwrap_err();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                AsmError::Symbol(SymbolError::OutOfRange(40000, _)),
+                AsmError::Symbol(SymbolError::OutOfRange(40001, _)),
+            ]
+        ));
+    }
+
+    #[test]
+    pub fn test_rust_source() {
+        let assembler = Assembler::new("(LOOP)\n@LOOP\n0;JMP\n".as_bytes());
+
+        let mut out = Vec::new();
+        assembler.assemble_to(&mut out, OutputFormat::RustSource).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("pub static PROGRAM: [u16; 2] = [\n    0x0000, 0xEA87,\n];\n"));
+        assert!(out.ends_with("pub const LOOP: u16 = 0; // label\n"));
+    }
+
+    #[test]
+    pub fn test_write_symbols() {
+        let assembler = Assembler::new("(START)\n@n\nM=0\n@START\n0;JMP\n".as_bytes());
+
+        let mut out = Vec::new();
+        assembler.write_symbols(&mut out, SymbolFormat::Sym).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let defined: Vec<&str> = out.lines().filter(|l| !l.contains("predefined")).collect();
+        assert_eq!(defined, vec!["START      0 label      1", "n         16 variable   2"]);
+
+        //no symbols are written when the source has errors
+        let assembler = Assembler::new("(START)\n(START)\n".as_bytes());
+        assert!(assembler.write_symbols(&mut Vec::new(), SymbolFormat::Json).is_err());
+    }
+
+    #[test]
+    pub fn test_listing() {
+        let src = "(LOOP)\n  @LOOP // back\n  0;JMP\n";
+
+        let mut out = Vec::new();
+        Assembler::new(src.as_bytes()).write_listing(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let lines: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "    0                                 (LOOP)",
+                "    0  0000000000000000  0000      0    @LOOP // back",
+                "    1  1110101010000111  EA87           0;JMP",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_strict_mode() {
+        let src = "@count\nM=0\n";
+
+        let options = Options {
+            symbol_mode: SymbolMode::Strict,
+            ..Options::default()
+        };
+        let errors = Assembler::with_options(src.as_bytes(), options)
+            .assemble_to_words()
+            .unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [AsmError::Symbol(SymbolError::UnresolvedSymbol(..))]
+        ));
+
+        let words = Assembler::new(src.as_bytes()).assemble_to_words().unwrap();
+        assert_eq!(words, vec![16, 0b1110101010001000]);
+    }
+
+    #[test]
+    pub fn test_unreadable_source() {
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let assembler = Assembler::new(BufReader::new(Broken));
+        let errors = assembler.assemble_to_words().unwrap_err();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [AsmError::Read(std::io::ErrorKind::BrokenPipe)]
+        ));
+    }
+
+    #[test]
+    pub fn test_unwritable_output() {
+        let assembler = Assembler::new("@2\n".as_bytes());
+
+        let mut out = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        out.push("no_such_dir");
+        out.push("out.hack");
+        let errors = assembler.assemble(out).unwrap_err();
+
+        assert!(matches!(errors.as_slice(), [AsmError::Io(_)]));
+    }
+
+    #[test]
+    pub fn test_collects_all_errors() {
+        let src = "D=M+D\n@x\n0;JXX\n(LOOP\nM=1\n";
+
+        let mut out = std::env::temp_dir();
+        out.push("hacky_collects_all_errors.hack");
+
+        let assembler = Assembler::new(src.as_bytes());
+        let errors = assembler.assemble(out.clone()).unwrap_err();
+
+        let lines: Vec<u32> = errors.iter().map(|e| e.span().line).collect();
+        assert_eq!(lines, vec![1, 3, 4]);
+        assert!(matches!(errors[0], AsmError::Parse(ParseError::InvalidCmp(..))));
+        assert!(matches!(errors[2], AsmError::Parse(ParseError::InvalidLabel(..))));
+
+        //nothing is written when there are errors
+        assert!(!out.exists());
+    }
+
+    #[test]
+    pub fn test_label_used_as_data_warns() {
+        let assembler = Assembler::new("(END)\n@END\nM=0\n@END\n0;JMP\n".as_bytes());
 
-        assert!(assembler.assemble(out).is_ok());
+        assert!(matches!(
+            assembler.warnings(),
+            [SymbolWarning::LabelAsData(name, ..)] if name == "END"
+        ));
+        assert_eq!(
+            assembler.assemble_to_words().unwrap(),
+            vec![0, 0b1110101010001000, 0, 0b1110101010000111]
+        );
     }
 }
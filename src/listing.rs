@@ -0,0 +1,114 @@
+use std::io::{self, Write};
+
+//one statement of the listing, a label has no word and is shown at the address it resolves to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub line: u32,
+    pub address: u16,
+    pub word: Option<u16>,
+    //the value an @symbol resolved to, or of a constant or alias
+    pub value: Option<u16>,
+    //the command for statements from a macro expansion, they are listed under the line of the call
+    pub expanded: Option<String>,
+}
+
+const HEADER: &str = " addr  binary            hex    value  source";
+
+//every source line is written, lines without a statement (comments, blank lines) only show the source
+//e.g.
+//    4  1110110000010000  EC10         D=A
+//    5  0000000000010000  0010     16  @i
+pub fn write_listing<W: Write>(w: &mut W, source: &str, entries: &[Entry]) -> io::Result<()> {
+    writeln!(w, "{HEADER}")?;
+
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let mut entries = entries.iter().peekable();
+
+    for (i, text) in source.lines().enumerate() {
+        let line = i as u32 + 1;
+        let text = text.trim_end_matches('\r');
+
+        //a line normally holds one statement, but block comments can join two onto one and a
+        //macro call expands to many
+        let mut on_line = Vec::new();
+        while let Some(e) = entries.next_if(|e| e.line == line) {
+            on_line.push(e);
+        }
+
+        let (columns, rest) = match on_line.split_first() {
+            Some((first, rest)) if first.expanded.is_none() => (format_entry(first), rest),
+            _ => (
+                " ".repeat(HEADER.find("source").unwrap() - 2),
+                on_line.as_slice(),
+            ),
+        };
+
+        writeln!(w, "{}", format!("{columns}  {text}").trim_end())?;
+
+        for e in rest {
+            let command = e.expanded.as_deref().unwrap_or("");
+            writeln!(
+                w,
+                "{}",
+                format!("{}    {command}", format_entry(e)).trim_end()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn format_entry(e: &Entry) -> String {
+    let (binary, hex) = match e.word {
+        Some(word) => (format!("{word:016b}"), format!("{word:04X}")),
+        None => (String::new(), String::new()),
+    };
+    let value = e.value.map_or(String::new(), |v| v.to_string());
+
+    format!("{:>5}  {binary:16}  {hex:4}  {value:>5}", e.address)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn listing_columns() {
+        let source = "// add\n@i\n\nD=A\n(END)\n";
+        let entries = [
+            Entry {
+                line: 2,
+                address: 0,
+                word: Some(16),
+                value: Some(16),
+                expanded: None,
+            },
+            Entry {
+                line: 4,
+                address: 1,
+                word: Some(0xEC10),
+                value: None,
+                expanded: None,
+            },
+            Entry {
+                line: 5,
+                address: 2,
+                word: None,
+                value: None,
+                expanded: None,
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_listing(&mut out, source, &entries).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            " addr  binary            hex    value  source\n\
+             \x20                                      // add\n\
+             \x20   0  0000000000010000  0010     16  @i\n\n\
+             \x20   1  1110110000010000  EC10         D=A\n\
+             \x20   2                                 (END)\n"
+        );
+    }
+}
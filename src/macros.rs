@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+
+use crate::instruction::{Comp, Dest, Jump};
+use crate::lexer::{self, Token, TokenKind};
+use crate::parser::ParseError;
+use crate::span::Span;
+
+//far more than fits in rom, stops macros that call each other several times from blowing up
+pub const MAX_EXPANDED_LINES: usize = 100_000;
+
+//words a line can start with that mean something else, a macro with one of these names would
+//take over every line using it
+const RESERVED_NAMES: [&str; 16] = [
+    "goto", "if", ".macro", ".endm", ".include", ".if", ".ifdef", ".ifndef", ".else", ".endif",
+    ".equ", ".define", ".alias", ".var", ".data", ".string",
+];
+
+//a macro defined with
+//
+//  .macro NAME param1, param2
+//      ...
+//  .endm
+//
+//and called with `NAME arg1, arg2`, every word of the body that matches a parameter is replaced
+//by the tokens of its argument
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Vec<Token>>,
+    //labels defined in the body, renamed for each expansion so a macro can be used twice
+    labels: Vec<String>,
+    //the name in the .macro line
+    span: Span,
+}
+
+#[derive(Default)]
+struct Expander {
+    macros: HashMap<String, Macro>,
+    //counts expansions, used to make the labels in each one unique
+    expansions: usize,
+    //the macros being expanded, outermost first, a name in here is being called from itself
+    active: Vec<String>,
+    //every line made by an expansion so far, once it's too many nothing else is expanded
+    expanded_lines: usize,
+    lines: Vec<Vec<Token>>,
+    errors: Vec<ParseError>,
+}
+
+fn is_directive(line: &[Token], name: &str) -> bool {
+    line[0].kind == TokenKind::Word && line[0].text == name
+}
+
+//splits tokens on commas, nothing at all is no arguments rather than one empty one
+fn split_args(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    tokens.split(|t| t.is_punct(',')).collect()
+}
+
+//removes the macro definitions from the lines and replaces every call with the lines of the
+//macro body, done before parsing so the assembler only ever sees plain commands
+//the expanded tokens keep the spans of the macro body and also point back at the call
+pub fn expand(lines: Vec<Vec<Token>>) -> (Vec<Vec<Token>>, Vec<ParseError>) {
+    let mut e = Expander::default();
+    let mut lines = lines.into_iter();
+
+    while let Some(line) = lines.next() {
+        if is_directive(&line, ".macro") {
+            e.define(line, &mut lines);
+        } else if is_directive(&line, ".endm") {
+            e.errors.push(ParseError::InvalidDirective(
+                line[0].text.clone(),
+                lexer::span_of(&line).unwrap(),
+            ));
+        } else {
+            e.emit(line);
+        }
+    }
+
+    (e.lines, e.errors)
+}
+
+impl Expander {
+    //reads a definition up to its .endm, a bad header still skips the body
+    fn define(&mut self, header: Vec<Token>, lines: &mut impl Iterator<Item = Vec<Token>>) {
+        let span = lexer::span_of(&header).unwrap();
+
+        let mut body = Vec::new();
+        let mut closed = false;
+        for line in lines.by_ref() {
+            if is_directive(&line, ".endm") {
+                closed = true;
+                break;
+            }
+
+            if is_directive(&line, ".macro") {
+                self.errors.push(ParseError::InvalidDirective(
+                    line[0].text.clone(),
+                    lexer::span_of(&line).unwrap(),
+                ));
+                continue;
+            }
+
+            body.push(line);
+        }
+
+        let Some((name, params)) = parse_header(&header) else {
+            self.errors
+                .push(ParseError::InvalidDirective(header[0].text.clone(), span));
+            return;
+        };
+
+        if is_reserved(&name) {
+            self.errors
+                .push(ParseError::ReservedMacroName(name, header[1].span));
+            return;
+        }
+
+        if !closed {
+            self.errors.push(ParseError::UnterminatedMacro(name, span));
+            return;
+        }
+
+        if let Some(first) = self.macros.get(&name) {
+            self.errors
+                .push(ParseError::DuplicateMacro(name, header[1].span, first.span));
+            return;
+        }
+
+        let labels = body
+            .iter()
+            .filter_map(|line| match line.as_slice() {
+                [open, label, close]
+                    if open.is_punct('(')
+                        && close.is_punct(')')
+                        && label.kind == TokenKind::Word =>
+                {
+                    Some(label.text.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        self.macros.insert(
+            name,
+            Macro {
+                params,
+                body,
+                labels,
+                span: header[1].span,
+            },
+        );
+    }
+
+    fn emit(&mut self, line: Vec<Token>) {
+        let first = &line[0];
+
+        if first.kind == TokenKind::Word && self.macros.contains_key(&first.text) {
+            self.call(&line);
+        } else {
+            self.lines.push(line);
+        }
+    }
+
+    fn call(&mut self, line: &[Token]) {
+        let name = &line[0].text;
+        let call = lexer::span_of(line).unwrap();
+        let m = self.macros[name].clone();
+
+        let args = split_args(&line[1..]);
+        if args.len() != m.params.len() || args.iter().any(|a| a.is_empty()) {
+            self.errors.push(ParseError::MacroArguments(
+                name.clone(),
+                m.params.len(),
+                args.len(),
+                call,
+            ));
+            return;
+        }
+
+        if self.active.contains(name) {
+            self.errors
+                .push(ParseError::RecursiveMacro(name.clone(), call));
+            return;
+        }
+
+        if self.expanded_lines >= MAX_EXPANDED_LINES {
+            return;
+        }
+
+        let expansion = self.expansions;
+        self.expansions += 1;
+        self.active.push(name.clone());
+
+        for body_line in m.body.iter() {
+            //reported once, by the call that went over
+            self.expanded_lines += 1;
+            if self.expanded_lines == MAX_EXPANDED_LINES {
+                self.errors
+                    .push(ParseError::MacroTooLarge(name.clone(), call));
+            }
+            if self.expanded_lines >= MAX_EXPANDED_LINES {
+                break;
+            }
+
+            let mut expanded = Vec::new();
+
+            for t in body_line {
+                if t.kind == TokenKind::Word {
+                    if let Some(i) = m.params.iter().position(|p| *p == t.text) {
+                        //arguments keep the spans they have at the call
+                        expanded.extend(args[i].iter().cloned());
+                        continue;
+                    }
+
+                    if m.labels.contains(&t.text) {
+                        expanded.push(Token {
+                            text: format!("{name}${expansion}.{}", t.text),
+                            span: t.span.expanded_from(call),
+                            ..t.clone()
+                        });
+                        continue;
+                    }
+                }
+
+                expanded.push(Token {
+                    span: t.span.expanded_from(call),
+                    ..t.clone()
+                });
+            }
+
+            //a parameter can be the whole line, so an empty argument could leave nothing
+            if !expanded.is_empty() {
+                self.emit(expanded);
+            }
+        }
+
+        self.active.pop();
+    }
+}
+
+//a name like D or JMP would make a c-instruction that starts with it a call
+fn is_reserved(name: &str) -> bool {
+    RESERVED_NAMES.contains(&name)
+        || Dest::from_mnemonic(name).is_some()
+        || Comp::from_mnemonic(name).is_some()
+        || Jump::from_mnemonic(name).is_some()
+}
+
+//.macro NAME a, b, c gives the name and the parameters
+fn parse_header(header: &[Token]) -> Option<(String, Vec<String>)> {
+    let name = header.get(1)?;
+    if !lexer::is_symbol(&name.text) {
+        return None;
+    }
+
+    let params = split_args(&header[2..])
+        .into_iter()
+        .map(|p| match p {
+            [p] if lexer::is_symbol(&p.text) => Some(p.text.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<String>>>()?;
+
+    Some((name.text.clone(), params))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::span::CallSite;
+
+    fn expand_source(src: &str) -> (Vec<String>, Vec<Vec<Token>>, Vec<ParseError>) {
+        let (lines, errors) = expand(lexer::tokenize(src).lines);
+        let texts = lines.iter().map(|l| lexer::text_of(l)).collect();
+
+        (texts, lines, errors)
+    }
+
+    #[test]
+    fn parameters_and_labels() {
+        let src = ".macro INC_JMP reg, target\n\
+                   (top)\n\
+                   reg=reg+1\n\
+                   @target\n\
+                   0;JMP\n\
+                   .endm\n\
+                   INC_JMP D, END\n\
+                   INC_JMP M, top\n";
+        let (texts, lines, errors) = expand_source(src);
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            texts,
+            vec![
+                "(INC_JMP$0.top)",
+                "D=D+1",
+                "@END",
+                "0;JMP",
+                "(INC_JMP$1.top)",
+                "M=M+1",
+                "@top",
+                "0;JMP",
+            ]
+        );
+
+        //the body tokens are on their line in the macro and point back at the call
+        let eq = &lines[5][1];
+        assert_eq!((eq.span.line, eq.span.col), (3, 4));
+        assert_eq!(
+            eq.span.call_site,
+            Some(CallSite {
+                file: 0,
+                line: 8,
+                col: 1,
+                len: 14
+            })
+        );
+
+        //arguments are where they were written
+        assert_eq!(lines[5][0].span, Span::new(8, 9, 1));
+    }
+
+    #[test]
+    fn nested_calls_point_at_the_outer_call() {
+        let src = ".macro ONE\n@1\n.endm\n.macro TWO\nONE\nONE\n.endm\nTWO\n";
+        let (texts, lines, errors) = expand_source(src);
+
+        assert!(errors.is_empty());
+        assert_eq!(texts, vec!["@1", "@1"]);
+        assert_eq!(lines[1][1].span.line, 2);
+        assert_eq!(lines[1][1].span.call_site.unwrap().line, 8);
+    }
+
+    #[test]
+    fn errors() {
+        let src = ".macro ONE x\n@x\n.endm\n\
+                   ONE\n\
+                   .macro ONE\n.endm\n\
+                   .macro LOOP\nLOOP\n.endm\nLOOP\n\
+                   .macro TWICE\nTWICE\nTWICE\n.endm\nTWICE\n\
+                   .endm\n\
+                   .macro OPEN\n@1\n";
+        let (_, _, errors) = expand_source(src);
+
+        match errors.as_slice() {
+            [
+                ParseError::MacroArguments(a, 1, 0, _),
+                ParseError::DuplicateMacro(d, _, first),
+                ParseError::RecursiveMacro(r, _),
+                ParseError::RecursiveMacro(t1, s1),
+                ParseError::RecursiveMacro(t2, s2),
+                ParseError::InvalidDirective(endm, _),
+                ParseError::UnterminatedMacro(u, span),
+            ] => {
+                assert_eq!(a, "ONE");
+                assert_eq!(d, "ONE");
+                assert_eq!(*first, Span::new(1, 8, 3));
+                assert_eq!(r, "LOOP");
+                //each call of TWICE inside itself is caught straight away
+                assert_eq!((t1.as_str(), s1.line), ("TWICE", 12));
+                assert_eq!((t2.as_str(), s2.line), ("TWICE", 13));
+                assert_eq!(endm, ".endm");
+                assert_eq!(u, "OPEN");
+                assert_eq!(span.line, 17);
+            }
+            r => panic!("expected macro errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn reserved_names() {
+        let src = ".macro D\n@1\n.endm\n.macro AM x\n.endm\n.macro JMP\n.endm\n\
+                   .macro goto l\n.endm\n.macro .equ\n.endm\n.macro DONE\n.endm\n\
+                   D=1\n";
+        let (texts, _, errors) = expand_source(src);
+
+        //the c-instruction is left alone rather than expanding D
+        assert_eq!(texts, vec!["D=1"]);
+
+        let names: Vec<(&str, u32)> = errors
+            .iter()
+            .map(|e| match e {
+                ParseError::ReservedMacroName(name, span) => (name.as_str(), span.line),
+                e => panic!("expected reserved macro names, got {e:?}"),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![("D", 1), ("AM", 4), ("JMP", 6), ("goto", 8), (".equ", 10)]
+        );
+    }
+
+    #[test]
+    fn expansion_limit() {
+        //each macro calls the one before it twice, so M20 would be a million lines
+        let mut src = String::from(".macro M0\n@1\n.endm\n");
+        for i in 1..=20 {
+            src.push_str(&format!(".macro M{i}\nM{}\nM{}\n.endm\n", i - 1, i - 1));
+        }
+        src.push_str("M20\n@2\n");
+
+        let (texts, _, errors) = expand_source(&src);
+
+        assert!(matches!(errors.as_slice(), [ParseError::MacroTooLarge(..)]));
+        assert!(texts.len() < MAX_EXPANDED_LINES);
+        assert_eq!(texts.last().unwrap(), "@2");
+    }
+}
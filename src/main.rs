@@ -1,43 +1,130 @@
 use std::fs;
 use std::io::BufReader;
 use std::path::PathBuf;
+use std::process;
 
-use hacky::Assembler;
+use hacky::diagnostic::Diagnostic;
+use hacky::emulator::ROM_SIZE;
+use hacky::symbol::SymbolMode;
+use hacky::output::{OutputFormat, SymbolFormat};
+use hacky::{AsmError, Assembler, Options};
+use hacky::{disasm, hack};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None, override_usage = "hacky <SRC> --out <OUT>")]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    override_usage = "hacky <SRC> [--out <OUT>] [--format <FORMAT>] [--rom-size <WORDS>] [--strict] [--symbols <FILE>] [--listing <FILE>] [-I <DIR>]... [-D <NAME=VALUE>]...\n       hacky disasm <SRC> [--out <OUT>] [--raw]"
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// path to the .asm file
     #[arg(required = true, index = 1)]
-    src: PathBuf,
+    src: Option<PathBuf>,
 
-    /// path to place assembled .hack file
+    /// path to place the assembled file
     #[arg(short, long)]
     out: Option<PathBuf>,
+
+    /// output format: hack, bin (big-endian), bin-le, ihex (intel hex), hex (one word per line),
+    /// readmemb, readmemh, vhdl, logisim, rust (a module with a static array) or c (a header)
+    #[arg(short, long, default_value = "hack")]
+    format: OutputFormat,
+
+    /// words of rom, at most 32768, the readmemb, readmemh, vhdl and logisim images are padded
+    /// to this size
+    #[arg(long, value_name = "WORDS", default_value_t = ROM_SIZE, value_parser = parse_rom_size)]
+    rom_size: usize,
+
+    /// treat an unknown @name as an error instead of allocating a variable for it
+    #[arg(long)]
+    strict: bool,
+
+    /// write the symbol table to a file, as json if it ends in .json and as text otherwise
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+
+    /// write a listing of each source line with its address and encoding
+    #[arg(long, value_name = "FILE")]
+    listing: Option<PathBuf>,
+
+    /// directory to look for .include files in, after the directory of the including file
+    #[arg(short = 'I', value_name = "DIR")]
+    include: Vec<PathBuf>,
+
+    /// define a symbol for .if conditions and @NAME, NAME on its own is NAME=1
+    #[arg(short = 'D', value_name = "NAME=VALUE", value_parser = parse_define)]
+    define: Vec<(String, u16)>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// turn a .hack file back into hack assembly
+    Disasm {
+        /// path to the .hack file
+        src: PathBuf,
+
+        /// path to place the .asm file, printed to stdout if none specified
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+
+        /// read src as raw big-endian 16-bit words instead of .hack text
+        #[arg(long)]
+        raw: bool,
+    },
 }
 
 
 fn main() {
     let args = Args::parse();
 
-    println!("src - {:?} out - {:?}", args.src, args.out);
-    
-    if !args.src.is_file() {
+    match args.command {
+        Some(Commands::Disasm { src, out, raw }) => disassemble(src, out, raw),
+        None => assemble(args),
+    }
+}
+
+fn assemble(args: Args) {
+    //clap makes src required when there is no subcommand
+    let src = args.src.unwrap();
+    let out = args.out;
+    let format = args.format;
+
+    let mut options = Options {
+        symbol_mode: if args.strict {
+            SymbolMode::Strict
+        } else {
+            SymbolMode::AutoVariables
+        },
+        rom_size: args.rom_size,
+        include_dirs: args.include,
+        ..Options::default()
+    };
+    options.predefined.extend(args.define);
+
+    if !src.is_file() {
         println!(".asm file missing");
         return;
     }
 
-    if args.src.extension() != Some(std::ffi::OsStr::new("asm")) {
+    if src.extension() != Some(std::ffi::OsStr::new("asm")) {
         println!(".asm file required for input");
         return;
     }
 
-    let out = match args.out {
+    let out = match out {
         Some(p) => {
-            if p.extension() != Some(std::ffi::OsStr::new("hack")) {
+            //other formats go to whatever file the toolchain expects
+            let is_hack = p.extension() == Some(std::ffi::OsStr::new("hack"));
+            if format == OutputFormat::Hack && !is_hack {
                 println!(".hack file required for output");
                 return;
             }
@@ -47,19 +134,133 @@ fn main() {
         }
         None => {
             let mut base = std::env::current_dir().unwrap();
-            base.push(&args.src.file_name().unwrap());
+            base.push(src.file_name().unwrap());
+            base.set_extension(format.extension());
             base
         },
     };
 
-    let file = fs::File::open(args.src).expect("failed to open asm file");
+    let file_name = src.display().to_string();
+    let assembler = match Assembler::from_file(&src, options) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("error: could not read `{file_name}`: {e}");
+            process::exit(1);
+        }
+    };
+
+    for w in assembler.warnings().iter() {
+        eprintln!(
+            "{}",
+            Diagnostic::from(w).render_sources(assembler.sources())
+        );
+    }
 
-    let reader = BufReader::new(file);
+    let result = assembler
+        .assemble_as(out, format)
+        .and_then(|_| match args.symbols {
+            Some(p) => write_symbols(&assembler, p),
+            None => Ok(()),
+        })
+        .and_then(|_| match args.listing {
+            Some(p) => write_listing(&assembler, p),
+            None => Ok(()),
+        });
 
-    let mut assembler = Assembler::new(reader).unwrap();
+    if let Err(errors) = result {
+        for e in errors.iter() {
+            eprintln!("{}", Diagnostic::from(e).render_sources(assembler.sources()));
+        }
 
-    match assembler.assemble(out) {
-        Ok(()) => {}
-        Err(e) => println!("{e:?}"),
+        eprintln!(
+            "error: could not assemble `{file_name}` due to {} previous error{}",
+            errors.len(),
+            if errors.len() == 1 { "" } else { "s" }
+        );
+        process::exit(1);
     }
 }
+
+fn parse_define(s: &str) -> Result<(String, u16), String> {
+    let (name, value) = s.split_once('=').unwrap_or((s, "1"));
+
+    if !hacky::lexer::is_symbol(name) {
+        return Err(format!("`{name}` is not a valid symbol name"));
+    }
+
+    match value.parse::<u16>() {
+        Ok(v) if v <= 32767 => Ok((name.into(), v)),
+        _ => Err(format!("`{value}` is not a number between 0 and 32767")),
+    }
+}
+
+//an a-instruction can only jump to the first 32K words, so a bigger rom couldn't be used
+fn parse_rom_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(size) if size <= ROM_SIZE => Ok(size),
+        _ => Err(format!("`{s}` is not a number between 0 and {ROM_SIZE}")),
+    }
+}
+
+fn write_symbols(assembler: &Assembler, path: PathBuf) -> Result<(), Vec<AsmError>> {
+    let format = SymbolFormat::from_path(&path);
+    let mut file = fs::File::create(path).map_err(|e| vec![AsmError::Io(e.kind())])?;
+
+    assembler.write_symbols(&mut file, format)
+}
+
+fn write_listing(assembler: &Assembler, path: PathBuf) -> Result<(), Vec<AsmError>> {
+    let mut file = fs::File::create(path).map_err(|e| vec![AsmError::Io(e.kind())])?;
+
+    assembler.write_listing(&mut file)
+}
+
+fn disassemble(src: PathBuf, out: Option<PathBuf>, raw: bool) {
+    let name = src.display().to_string();
+
+    let words = if raw {
+        let bytes = match fs::read(&src) {
+            Ok(b) => b,
+            Err(e) => fail(format!("could not read `{name}`: {e}")),
+        };
+
+        if !bytes.len().is_multiple_of(2) {
+            fail(format!("`{name}` must be a whole number of 16-bit words"));
+        }
+
+        bytes
+            .chunks(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .collect()
+    } else {
+        let file = match fs::File::open(&src) {
+            Ok(f) => f,
+            Err(e) => fail(format!("could not read `{name}`: {e}")),
+        };
+
+        match hack::read_words(BufReader::new(file)) {
+            Ok(w) => w,
+            Err(e) => fail(format!("could not read `{name}`, {e}")),
+        }
+    };
+
+    let asm = match disasm::disassemble(&words) {
+        Ok(a) => a,
+        Err(e) => fail(format!("could not disassemble `{name}`: {e}")),
+    };
+
+    match out {
+        Some(p) => {
+            if let Err(e) = fs::write(&p, asm) {
+                fail(format!("could not write `{}`: {e}", p.display()));
+            }
+        }
+        None => print!("{asm}"),
+    }
+}
+
+//errors that stop the disassembler, printed like the assembler's summary line
+fn fail(message: String) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
+}
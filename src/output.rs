@@ -0,0 +1,594 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::codegen;
+use crate::emulator::ROM_SIZE;
+use crate::source::Sources;
+use crate::span::Span;
+use crate::symbol::SymbolTable;
+
+//the formats written in hex all use uppercase digits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    //one 16 character binary string per line, the format the nand2tetris tools use
+    #[default]
+    Hack,
+    //raw 16-bit words, big-endian
+    BinaryBe,
+    //raw 16-bit words, little-endian
+    BinaryLe,
+    //intel hex records of the big-endian bytes, addressed by byte
+    IntelHex,
+    //one 4 digit hex word per line
+    HexText,
+    //verilog $readmemb file, one binary word per line, padded to the rom size
+    ReadMemB,
+    //verilog $readmemh file, one hex word per line, padded to the rom size
+    ReadMemH,
+    //vhdl package with the rom as a constant array
+    Vhdl,
+    //logisim memory image, "v2.0 raw" followed by the words in hex
+    Logisim,
+    //rust module with a static PROGRAM array and a const for each symbol
+    RustSource,
+    //c header with a const uint16_t array and a define for each symbol
+    CHeader,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 11] = [
+        OutputFormat::Hack,
+        OutputFormat::BinaryBe,
+        OutputFormat::BinaryLe,
+        OutputFormat::IntelHex,
+        OutputFormat::HexText,
+        OutputFormat::ReadMemB,
+        OutputFormat::ReadMemH,
+        OutputFormat::Vhdl,
+        OutputFormat::Logisim,
+        OutputFormat::RustSource,
+        OutputFormat::CHeader,
+    ];
+
+    //the name used to pick the format on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Hack => "hack",
+            OutputFormat::BinaryBe => "bin",
+            OutputFormat::BinaryLe => "bin-le",
+            OutputFormat::IntelHex => "ihex",
+            OutputFormat::HexText => "hex",
+            OutputFormat::ReadMemB => "readmemb",
+            OutputFormat::ReadMemH => "readmemh",
+            OutputFormat::Vhdl => "vhdl",
+            OutputFormat::Logisim => "logisim",
+            OutputFormat::RustSource => "rust",
+            OutputFormat::CHeader => "c",
+        }
+    }
+
+    //extension of the default output file
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Hack => "hack",
+            OutputFormat::BinaryBe | OutputFormat::BinaryLe => "bin",
+            OutputFormat::IntelHex => "hex",
+            OutputFormat::HexText => "txt",
+            OutputFormat::ReadMemB | OutputFormat::ReadMemH => "mem",
+            OutputFormat::Vhdl => "vhd",
+            OutputFormat::Logisim => "img",
+            OutputFormat::RustSource => "rs",
+            OutputFormat::CHeader => "h",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = Self::ALL.iter().map(|f| f.name()).collect();
+                format!("unknown format `{s}`, expected one of {}", names.join(", "))
+            })
+    }
+}
+
+pub fn write_words<W: Write>(w: &mut W, words: &[u16], format: OutputFormat) -> io::Result<()> {
+    write_rom(w, words, format, ROM_SIZE)
+}
+
+//everything the assembler can write, the rust and c sources get a constant for each symbol
+pub fn write_program<W: Write>(
+    w: &mut W,
+    words: &[u16],
+    symbols: &SymbolTable,
+    format: OutputFormat,
+    rom_size: usize,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::RustSource => codegen::write_rust(w, words, symbols),
+        OutputFormat::CHeader => codegen::write_c(w, words, symbols),
+        _ => write_rom(w, words, format, rom_size),
+    }
+}
+
+//like write_words, but the rom images for hardware are padded with zeros to rom_size words
+//a program that doesn't fit in the rom is an InvalidInput error
+pub fn write_rom<W: Write>(
+    w: &mut W,
+    words: &[u16],
+    format: OutputFormat,
+    rom_size: usize,
+) -> io::Result<()> {
+    if words.len() > rom_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "program is {} words but the rom only holds {rom_size}",
+                words.len()
+            ),
+        ));
+    }
+    let padding = rom_size - words.len();
+
+    match format {
+        OutputFormat::Hack => {
+            let lines: Vec<String> = words.iter().map(|w| format!("{:016b}", w)).collect();
+            write!(w, "{}", lines.join("\n"))
+        }
+
+        OutputFormat::BinaryBe => {
+            let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+            w.write_all(&bytes)
+        }
+
+        OutputFormat::BinaryLe => {
+            let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+            w.write_all(&bytes)
+        }
+
+        OutputFormat::IntelHex => write_intel_hex(w, words),
+
+        OutputFormat::HexText => {
+            for word in words {
+                writeln!(w, "{word:04X}")?;
+            }
+            Ok(())
+        }
+
+        OutputFormat::ReadMemB => {
+            writeln!(w, "// hack rom, {} words, {rom_size} deep", words.len())?;
+            for word in words.iter().chain(std::iter::repeat_n(&0, padding)) {
+                writeln!(w, "{word:016b}")?;
+            }
+            Ok(())
+        }
+
+        OutputFormat::ReadMemH => {
+            writeln!(w, "// hack rom, {} words, {rom_size} deep", words.len())?;
+            for word in words.iter().chain(std::iter::repeat_n(&0, padding)) {
+                writeln!(w, "{word:04X}")?;
+            }
+            Ok(())
+        }
+
+        OutputFormat::Vhdl => write_vhdl(w, words, rom_size),
+
+        OutputFormat::Logisim => write_logisim(w, words, padding),
+
+        //without a symbol table there are no constants, just the program
+        OutputFormat::RustSource => codegen::write_rust(w, words, &SymbolTable::new()),
+        OutputFormat::CHeader => codegen::write_c(w, words, &SymbolTable::new()),
+    }
+}
+
+//the unused part of the rom is filled by the others clause
+fn write_vhdl<W: Write>(w: &mut W, words: &[u16], rom_size: usize) -> io::Result<()> {
+    writeln!(w, "library ieee;")?;
+    writeln!(w, "use ieee.std_logic_1164.all;")?;
+    writeln!(w)?;
+    writeln!(w, "package hack_rom is")?;
+    writeln!(w, "    constant ROM_SIZE : natural := {rom_size};")?;
+    writeln!(
+        w,
+        "    type rom_t is array (0 to ROM_SIZE - 1) of std_logic_vector(15 downto 0);"
+    )?;
+    writeln!(w, "    constant ROM : rom_t := (")?;
+
+    for (i, word) in words.iter().enumerate() {
+        writeln!(w, "        {i} => \"{word:016b}\",")?;
+    }
+
+    writeln!(w, "        others => (others => '0')")?;
+    writeln!(w, "    );")?;
+    writeln!(w, "end package hack_rom;")
+}
+
+//words per line, logisim reads any whitespace so this is only for looks
+const LOGISIM_LINE_LEN: usize = 8;
+
+//the trailing zeros use logisim's run length syntax, N*value
+fn write_logisim<W: Write>(w: &mut W, words: &[u16], padding: usize) -> io::Result<()> {
+    writeln!(w, "v2.0 raw")?;
+
+    for line in words.chunks(LOGISIM_LINE_LEN) {
+        let line: Vec<String> = line.iter().map(|word| format!("{word:X}")).collect();
+        writeln!(w, "{}", line.join(" "))?;
+    }
+
+    if padding > 0 {
+        writeln!(w, "{padding}*0")?;
+    }
+
+    Ok(())
+}
+
+//bytes per data record, the usual choice
+const IHEX_RECORD_LEN: usize = 16;
+
+//records only hold 16-bit addresses, so past the first 64K bytes each 64K block starts with an
+//extended linear address record giving the upper 16 bits
+fn write_intel_hex<W: Write>(w: &mut W, words: &[u16]) -> io::Result<()> {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_be_bytes()).collect();
+
+    for (i, chunk) in bytes.chunks(IHEX_RECORD_LEN).enumerate() {
+        let address = i * IHEX_RECORD_LEN;
+        let upper = (address >> 16) as u16;
+        if upper > 0 && address & 0xFFFF == 0 {
+            write_ihex_record(w, 0, 0x04, &upper.to_be_bytes())?;
+        }
+
+        write_ihex_record(w, address as u16, 0x00, chunk)?;
+    }
+
+    //end of file
+    write_ihex_record(w, 0, 0x01, &[])
+}
+
+//:LLAAAATT<data>CC, where the checksum makes all the bytes sum to zero
+fn write_ihex_record<W: Write>(w: &mut W, address: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let [hi, lo] = address.to_be_bytes();
+    let mut record = vec![data.len() as u8, hi, lo, kind];
+    record.extend_from_slice(data);
+
+    let sum = record.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+    record.push(sum.wrapping_neg());
+
+    let hex: String = record.iter().map(|b| format!("{b:02X}")).collect();
+    writeln!(w, ":{hex}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymbolFormat {
+    //one symbol per line: name, address, kind and the file and line it was defined on
+    #[default]
+    Sym,
+    Json,
+}
+
+impl SymbolFormat {
+    //json for a .json file, the text format for anything else
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => SymbolFormat::Json,
+            _ => SymbolFormat::Sym,
+        }
+    }
+}
+
+//writes every symbol in definition order, predefined symbols have no line
+//the file is named as in diagnostics, a source given as a string has no name so only the line
+//is written
+pub fn write_symbols<W: Write>(
+    w: &mut W,
+    table: &SymbolTable,
+    sources: &Sources,
+    format: SymbolFormat,
+) -> io::Result<()> {
+    let file = |span: Span| Some(sources.name(span.file)).filter(|n| !n.is_empty());
+
+    match format {
+        SymbolFormat::Sym => {
+            let width = table.iter().map(|e| e.name.len()).max().unwrap_or(0);
+
+            for e in table.iter() {
+                let place = match e.span {
+                    Some(s) => match file(s) {
+                        Some(f) => format!("{f}:{}", s.line),
+                        None => s.line.to_string(),
+                    },
+                    None => "-".to_string(),
+                };
+                writeln!(
+                    w,
+                    "{:<width$} {:5} {:<10} {}",
+                    e.name,
+                    e.address,
+                    e.kind.name(),
+                    place
+                )?;
+            }
+
+            Ok(())
+        }
+
+        SymbolFormat::Json => {
+            writeln!(w, "{{")?;
+            writeln!(w, "  \"symbols\": [")?;
+
+            let count = table.len();
+            for (i, e) in table.iter().enumerate() {
+                let path = e
+                    .span
+                    .and_then(file)
+                    .map_or("null".to_string(), json_string);
+                let line = e.span.map_or("null".to_string(), |s| s.line.to_string());
+                let comma = if i + 1 < count { "," } else { "" };
+
+                writeln!(
+                    w,
+                    "    {{\"name\": {}, \"address\": {}, \"kind\": \"{}\", \"file\": {}, \
+                     \"line\": {}}}{comma}",
+                    json_string(&e.name),
+                    e.address,
+                    e.kind.name(),
+                    path,
+                    line
+                )?;
+            }
+
+            writeln!(w, "  ]")?;
+            writeln!(w, "}}")
+        }
+    }
+}
+
+//symbol names are plain ascii, but custom predefined symbols could hold anything
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::source::SourceFile;
+    use crate::symbol::SymbolKind;
+
+    #[test]
+    fn hack_text() {
+        let mut out = Vec::new();
+        write_words(&mut out, &[2, 0xEC10], OutputFormat::Hack).unwrap();
+
+        assert_eq!(out, b"0000000000000010\n1110110000010000");
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        let mut out = Vec::new();
+        write_words(&mut out, &[2, 0xEC10], OutputFormat::BinaryBe).unwrap();
+
+        assert_eq!(out, vec![0x00, 0x02, 0xEC, 0x10]);
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let mut out = Vec::new();
+        write_words(&mut out, &[2, 0xEC10], OutputFormat::BinaryLe).unwrap();
+
+        assert_eq!(out, vec![0x02, 0x00, 0x10, 0xEC]);
+    }
+
+    #[test]
+    fn intel_hex() {
+        let words: Vec<u16> = (0..9).collect();
+        let mut out = Vec::new();
+        write_words(&mut out, &words, OutputFormat::IntelHex).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            ":1000000000000001000200030004000500060007D4\n\
+             :020010000008E6\n\
+             :00000001FF\n"
+        );
+
+        //past 64K bytes the upper address goes in its own record
+        let words = vec![0; 32776];
+        let mut out = Vec::new();
+        write_rom(&mut out, &words, OutputFormat::IntelHex, 40000).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let records: Vec<&str> = out.lines().skip(4095).collect();
+
+        assert_eq!(
+            records,
+            vec![
+                ":10FFF0000000000000000000000000000000000001",
+                ":020000040001F9",
+                ":1000000000000000000000000000000000000000F0",
+                ":00000001FF",
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_text() {
+        let mut out = Vec::new();
+        write_words(&mut out, &[2, 0xEC10], OutputFormat::HexText).unwrap();
+        assert_eq!(out, b"0002\nEC10\n");
+    }
+
+    #[test]
+    fn hex_formats_are_uppercase() {
+        for format in [
+            OutputFormat::IntelHex,
+            OutputFormat::HexText,
+            OutputFormat::ReadMemH,
+            OutputFormat::Logisim,
+        ] {
+            let mut out = Vec::new();
+            write_rom(&mut out, &[0xEC10, 0xABCD], format, 2).unwrap();
+            let text = String::from_utf8(out).unwrap();
+
+            assert!(text.contains("EC10"), "{format:?}: {text}");
+            assert!(!text.contains("ec10"), "{format:?}: {text}");
+        }
+    }
+
+    #[test]
+    fn verilog_padded() {
+        let mut out = Vec::new();
+        write_rom(&mut out, &[2, 0xEC10], OutputFormat::ReadMemB, 3).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "// hack rom, 2 words, 3 deep\n\
+             0000000000000010\n\
+             1110110000010000\n\
+             0000000000000000\n"
+        );
+
+        let mut out = Vec::new();
+        write_rom(&mut out, &[2, 0xEC10], OutputFormat::ReadMemH, 4).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "// hack rom, 2 words, 4 deep\n0002\nEC10\n0000\n0000\n"
+        );
+    }
+
+    #[test]
+    fn program_larger_than_rom() {
+        let mut out = Vec::new();
+        let e = write_rom(&mut out, &[2, 0xEC10], OutputFormat::ReadMemB, 1).unwrap_err();
+
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn vhdl_package() {
+        let mut out = Vec::new();
+        write_rom(&mut out, &[2, 0xEC10], OutputFormat::Vhdl, 1024).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains("    constant ROM_SIZE : natural := 1024;\n"));
+        assert!(out.contains(
+            "        0 => \"0000000000000010\",\n\
+             \x20       1 => \"1110110000010000\",\n\
+             \x20       others => (others => '0')\n"
+        ));
+        assert!(out.ends_with("end package hack_rom;\n"));
+    }
+
+    #[test]
+    fn logisim_image() {
+        let words: Vec<u16> = (0..10).map(|i| i * 0x111).collect();
+        let mut out = Vec::new();
+        write_rom(&mut out, &words, OutputFormat::Logisim, 16).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "v2.0 raw\n\
+             0 111 222 333 444 555 666 777\n\
+             888 999\n\
+             6*0\n"
+        );
+    }
+
+    #[test]
+    fn format_names() {
+        for f in OutputFormat::ALL {
+            assert_eq!(f.name().parse(), Ok(f));
+        }
+        assert!("elf".parse::<OutputFormat>().is_err());
+    }
+
+    fn small_table() -> SymbolTable {
+        let mut table = SymbolTable::with_predefined(&[("R0".into(), 0)]);
+        table.insert(
+            "LOOP".into(),
+            4,
+            SymbolKind::Label,
+            Some(Span::new(3, 2, 4)),
+        );
+        table.insert(
+            "i".into(),
+            16,
+            SymbolKind::Variable,
+            Some(Span {
+                file: 1,
+                ..Span::new(1, 2, 1)
+            }),
+        );
+        table
+    }
+
+    //the main file and the lib.asm it included
+    fn sources() -> Sources {
+        let mut sources = Sources::new("main.asm", "", None);
+        sources.add(SourceFile {
+            name: "lib.asm".into(),
+            text: String::new(),
+            path: None,
+            included_from: Some(Span::new(1, 1, 8)),
+        });
+        sources
+    }
+
+    #[test]
+    fn symbols_sym() {
+        let mut out = Vec::new();
+        write_symbols(&mut out, &small_table(), &sources(), SymbolFormat::Sym).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "R0       0 predefined -\n\
+             LOOP     4 label      main.asm:3\n\
+             i       16 variable   lib.asm:1\n"
+        );
+
+        //a source without a name only has lines
+        let mut out = Vec::new();
+        let unnamed = Sources::new("", "", None);
+        write_symbols(&mut out, &small_table(), &unnamed, SymbolFormat::Sym).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("LOOP     4 label      3\n")
+        );
+    }
+
+    #[test]
+    fn symbols_json() {
+        let mut out = Vec::new();
+        write_symbols(&mut out, &small_table(), &sources(), SymbolFormat::Json).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\n  \"symbols\": [\n\
+             \x20   {\"name\": \"R0\", \"address\": 0, \"kind\": \"predefined\", \"file\": null, \
+             \"line\": null},\n\
+             \x20   {\"name\": \"LOOP\", \"address\": 4, \"kind\": \"label\", \
+             \"file\": \"main.asm\", \"line\": 3},\n\
+             \x20   {\"name\": \"i\", \"address\": 16, \"kind\": \"variable\", \
+             \"file\": \"lib.asm\", \"line\": 1}\n\
+             \x20 ]\n}\n"
+        );
+
+        assert_eq!(json_string("a\"b\\"), "\"a\\\"b\\\\\"");
+    }
+}
@@ -1,36 +1,143 @@
-use std::io::{BufRead, Lines};
-use std::iter::Peekable;
-
-const VALID_COMPS: &[&str] = &[
-    "0", "1", "-1", "D", "A", "!D", "!A", "-D", "-A", "D+1", "A+1", "D-1", "A-1", "D+A", "D-A",
-    "A-D", "D&A", "D|A", "M", "!M", "-M", "M+1", "M-1", "D+M", "D-M", "M-D", "D&M", "D|M",
-];
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, ErrorKind};
+
+use crate::Options;
+use crate::data;
+use crate::expr::{self, Expr};
+use crate::include;
+use crate::instruction::{Comp, Dest, Instruction, Jump};
+use crate::lexer::{self, LexError, Token, TokenKind};
+use crate::macros;
+use crate::pseudo;
+use crate::source::Sources;
+use crate::span::Span;
+
+pub struct Parser {
+    lines: VecDeque<Vec<Token>>,
+    //errors from the lexer, includes, and macro, data and pseudo-instruction expansion, handed
+    //out by advance before any commands
+    front_errors: VecDeque<ParseError>,
+    end: Span,
+    current: Option<Statement>,
+}
 
-const VALID_JUMPS: &[&str] = &["JGT", "JEQ", "JGE", "JLT", "JNE", "JLE", "JMP"];
+#[derive(Debug, Clone)]
+pub enum ParseError {
+    InvalidLabel(String, Span),
+    InvalidDest(String, Span),
+    InvalidJmp(String, Span),
+    InvalidCmp(String, Span),
+    InvalidCommand(String, Span),
+    //a number as it was written that is too large for what it's used for
+    IntegerOverflow(String, NumberUse, Span),
+    //a word starting with a digit that isn't a decimal, 0x or 0b number
+    InvalidNumber(String, Span),
+    //the whole expression and the span of where it goes wrong
+    InvalidExpression(String, Span),
+    UnterminatedComment(Span),
+    UnterminatedString(Span),
+    EndOfFile(Span),
+    //a line starting with . that isn't a known directive, or one with the wrong operands
+    InvalidDirective(String, Span),
+    //the macro name and the span of its .macro
+    UnterminatedMacro(String, Span),
+    //the macro name, the second .macro and the first one
+    DuplicateMacro(String, Span, Span),
+    //a macro named like an instruction mnemonic, pseudo-instruction or directive, and the name
+    ReservedMacroName(String, Span),
+    //the macro name, how many parameters it has, how many arguments it was given and the call
+    MacroArguments(String, usize, usize, Span),
+    //a macro that ends up calling itself, the span of that call
+    RecursiveMacro(String, Span),
+    //macros that expand to too many lines, the macro and the call that went over
+    MacroTooLarge(String, Span),
+    //the path given to .include, why it couldn't be read and the span of the path
+    IncludeFailed(String, ErrorKind, Span),
+    //a file that is already being included, so would include itself forever
+    RecursiveInclude(String, Span),
+    //an .if, .ifdef or .ifndef with a condition that can't be read
+    InvalidCondition(Span),
+    //a name in a condition that isn't predefined or defined by an earlier .equ
+    UndefinedCondition(String, Span),
+    //the span of an .if with no .endif
+    UnterminatedIf(Span),
+    //an .if, .ifdef, .ifndef, .else or .endif in a macro body, and the .macro
+    ConditionInMacro(Span, Span),
+    //a goto, if or [addr] pseudo-instruction that isn't written right, goto, if or [ and the line
+    InvalidPseudo(String, Span),
+    //a computation in a pseudo-instruction that reads A or M after they were set to the address
+    ClobberedComp(String, Span),
+}
 
-const VALID_DESTS: &[&str] = &["M", "D", "DM", "A", "AM", "AD", "ADM"];
+//where a number was written, each place allows a different range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberUse {
+    AInstruction,
+    Data,
+    Constant,
+    VarSize,
+    VarAddress,
+}
 
-pub struct Parser<R: BufRead> {
-    lines: Peekable<Lines<R>>,
-    current: Option<Command>,
-    line_num: u32,
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::InvalidLabel(_, s)
+            | ParseError::InvalidDest(_, s)
+            | ParseError::InvalidJmp(_, s)
+            | ParseError::InvalidCmp(_, s)
+            | ParseError::InvalidCommand(_, s)
+            | ParseError::IntegerOverflow(_, _, s)
+            | ParseError::InvalidNumber(_, s)
+            | ParseError::InvalidExpression(_, s)
+            | ParseError::UnterminatedComment(s)
+            | ParseError::UnterminatedString(s)
+            | ParseError::EndOfFile(s)
+            | ParseError::InvalidDirective(_, s)
+            | ParseError::UnterminatedMacro(_, s)
+            | ParseError::DuplicateMacro(_, s, _)
+            | ParseError::ReservedMacroName(_, s)
+            | ParseError::MacroArguments(_, _, _, s)
+            | ParseError::RecursiveMacro(_, s)
+            | ParseError::MacroTooLarge(_, s)
+            | ParseError::IncludeFailed(_, _, s)
+            | ParseError::RecursiveInclude(_, s)
+            | ParseError::InvalidCondition(s)
+            | ParseError::UndefinedCondition(_, s)
+            | ParseError::UnterminatedIf(s)
+            | ParseError::ConditionInMacro(s, _)
+            | ParseError::InvalidPseudo(_, s)
+            | ParseError::ClobberedComp(_, s) => *s,
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
-pub enum ParseError {
-    InvalidLabel(String, u32),
-    InvalidDest(String, u32),
-    InvalidJmp(String, u32),
-    InvalidCmp(String, u32),
-    InvalidCommand(String),
-    IntegerOverflow(u16, u32),
-    EndOfFile,
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        match e {
+            LexError::UnterminatedComment(s) => ParseError::UnterminatedComment(s),
+            LexError::UnterminatedString(s) => ParseError::UnterminatedString(s),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Symbol {
     Decimal(u16),
     Name(String),
+    //anything more than a single number or name, worked out after the symbol table is built
+    Expr(Expr),
+}
+
+//a parsed command and where it came from, the assembler works on a list of these
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub command: Command,
+    //the whole command
+    pub span: Span,
+    //the symbol of an a-command or the name of a label, the whole command otherwise
+    pub name_span: Span,
 }
 
 #[derive(Debug, Clone)]
@@ -40,198 +147,400 @@ pub enum Command {
     },
 
     CCommand {
-        dest: Option<String>,
-        comp: String,
-        jmp: Option<String>,
+        dest: Option<Dest>,
+        comp: Comp,
+        jmp: Option<Jump>,
     },
 
     LCommand {
         label: String,
     },
+
+    //.equ NAME value or .define NAME value, a named number that takes no memory
+    Constant {
+        name: String,
+        value: u16,
+    },
+
+    //.alias NAME TARGET, another name for an existing symbol
+    Alias {
+        name: String,
+        target: String,
+    },
+
+    //.var NAME SIZE @ ADDR, size words of ram at a fixed address or the next free one
+    Var {
+        name: String,
+        size: u16,
+        at: Option<u16>,
+    },
 }
 
-impl<R: BufRead> Parser<R> {
-    pub fn new(reader: R) -> Self {
-        Parser {
-            lines: reader.lines().peekable(),
-            current: None,
-            line_num: 0,
-        }
+impl Command {
+    //a and c commands, the ones that become a word of the program
+    pub fn is_instruction(&self) -> bool {
+        matches!(self, Command::ACommand { .. } | Command::CCommand { .. })
     }
+}
 
-    pub fn get_current(&self) -> Option<&Command> {
-        self.current.as_ref()
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Decimal(d) => write!(f, "{d}"),
+            Symbol::Name(n) => f.write_str(n),
+            Symbol::Expr(e) => e.fmt(f),
+        }
     }
+}
+
+//formats as the hack source for the command
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::ACommand { symbol } => write!(f, "@{symbol}"),
+
+            Command::CCommand { dest, comp, jmp } => Instruction::C {
+                dest: dest.unwrap_or(Dest::Null),
+                comp: *comp,
+                jump: jmp.unwrap_or(Jump::Null),
+            }
+            .fmt(f),
+
+            Command::LCommand { label } => write!(f, "({label})"),
 
-    pub fn has_more_commands(&mut self) -> bool {
-        //skip lines that are comments or whitespace
-        //we can peek to check without advancing iterator
-        while let Some(line_result) = self.lines.peek() {
-            match line_result {
-                Ok(line) if line.trim().is_empty() || line.trim().starts_with("//") => {
-                    if let Some(Ok(l)) = self.lines.next() {
-                        println!("found comment/empty: {}", l);
-                    }
+            Command::Constant { name, value } => write!(f, ".equ {name} {value}"),
+
+            Command::Alias { name, target } => write!(f, ".alias {name} {target}"),
+
+            Command::Var { name, size, at } => {
+                write!(f, ".var {name}")?;
+                if *size != 1 {
+                    write!(f, " {size}")?;
+                }
+                match at {
+                    Some(at) => write!(f, " @ {at}"),
+                    None => Ok(()),
                 }
-                _ => break,
             }
         }
+    }
+}
 
-        //here we are either at end of file, or at a valid command (so far)
-        //return true if there is a command and it's valid string, will only parse when we actually advance
-        if let Some(l) = self.lines.peek() {
-            if l.is_ok() {
-                return true;
-            }
+//reads the whole source, anything that isn't utf-8 is replaced rather than failing
+pub(crate) fn read_source<R: BufRead>(mut reader: R) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+impl Parser {
+    pub fn new<R: BufRead>(reader: R) -> io::Result<Self> {
+        Ok(Self::from_source(&read_source(reader)?))
+    }
+
+    //includes are looked for relative to the current directory
+    pub fn from_source(source: &str) -> Self {
+        Self::from_sources(&mut Sources::new("", source, None), &Options::default())
+    }
+
+    //parses the main source of sources, adding every file it includes
+    //includes are looked for next to the including file and then in the include dirs, and
+    //conditions can use the predefined symbols
+    pub fn from_sources(sources: &mut Sources, options: &Options) -> Self {
+        let (lines, mut front_errors, end) = include::load(sources, options);
+        let (lines, macro_errors) = macros::expand(lines);
+        front_errors.extend(macro_errors);
+        let (lines, data_errors) = data::expand(lines);
+        front_errors.extend(data_errors);
+        let (lines, pseudo_errors) = pseudo::expand(lines);
+        front_errors.extend(pseudo_errors);
+
+        Parser {
+            lines: lines.into(),
+            front_errors: front_errors.into(),
+            end,
+            current: None,
         }
+    }
 
-        return false;
+    pub fn get_current(&self) -> Option<&Command> {
+        self.current.as_ref().map(|s| &s.command)
     }
 
-    //this should only be run after has_more_commands has been run
-    //next command should be valid string so no need to check
-    pub fn advance(&mut self) -> Result<(), ParseError> {
-        let mut command_str = self.lines.next().unwrap().unwrap();
-        command_str.retain(|c| !c.is_whitespace());
-
-        //need to also parse command here to ensure that it's valid
-        let command = match command_str.chars().next() {
-            Some('@') => match self.parse_a_command(&command_str, self.line_num) {
-                Ok(c) => c,
-                Err(e) => return Err(e),
-            },
-
-            Some('(') => match self.parse_l_command(&command_str, self.line_num) {
-                Ok(c) => c,
-                Err(e) => return Err(e),
-            },
-
-            Some('M') | Some('A') | Some('D') | Some('0') => {
-                match self.parse_c_command(&command_str, self.line_num) {
-                    Ok(c) => c,
-                    Err(e) => return Err(e),
-                }
-            }
+    pub fn get_current_statement(&self) -> Option<&Statement> {
+        self.current.as_ref()
+    }
 
-            Some(_) => {
-                return Err(ParseError::InvalidCommand(command_str));
-            }
+    pub fn has_more_commands(&self) -> bool {
+        //comments and blank lines are already gone, so anything left is a command (or an error)
+        !self.lines.is_empty() || !self.front_errors.is_empty()
+    }
 
-            None => {
-                return Err(ParseError::EndOfFile);
-            }
+    //parses the next line, on an error the line is still consumed so parsing can carry on
+    pub fn advance(&mut self) -> Result<(), ParseError> {
+        if let Some(e) = self.front_errors.pop_front() {
+            return Err(e);
+        }
+
+        let Some(tokens) = self.lines.pop_front() else {
+            return Err(ParseError::EndOfFile(self.end));
         };
 
-        self.current = Some(command);
-        self.line_num += 1;
-        println!("current command - {:?}", &self.current.as_ref().unwrap());
+        self.current = Some(parse_line(&tokens)?);
         Ok(())
     }
 
-    fn parse_a_command(&self, cmd: &str, line: u32) -> Result<Command, ParseError> {
-        let symbol = &cmd[1..];
+    //parses everything that's left, skipping bad lines so every error is found
+    pub fn parse_all(mut self) -> (Vec<Statement>, Vec<ParseError>) {
+        let mut statements = Vec::new();
+        let mut errors = Vec::new();
 
-        match symbol.parse::<u16>() {
-            
-            Ok(i) if i <= 32767 => Ok(Command::ACommand {
-                symbol: Symbol::Decimal(i),
-            }),
+        while self.has_more_commands() {
+            match self.advance() {
+                Ok(()) => statements.extend(self.current.take()),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        (statements, errors)
+    }
+}
 
-            Ok(i) => Err(ParseError::IntegerOverflow(i, line)),
+//parses one line of tokens, there is always at least one token
+fn parse_line(tokens: &[Token]) -> Result<Statement, ParseError> {
+    let first = &tokens[0];
+    let span = lexer::span_of(tokens).unwrap();
 
-            Err(_) => Ok(Command::ACommand {
-                symbol: Symbol::Name(symbol.to_string()),
-            }),
+    let command = match first.kind {
+        TokenKind::Punct('@') => parse_a_command(tokens)?,
+
+        TokenKind::Punct('(') => parse_l_command(tokens)?,
+
+        TokenKind::Word if first.text.starts_with('.') => parse_directive(tokens)?,
+
+        _ if first.text.starts_with(['M', 'A', 'D', '0', '1', '-', '!']) => {
+            parse_c_command(tokens)?
         }
+
+        _ => return Err(ParseError::InvalidCommand(lexer::text_of(tokens), span)),
+    };
+
+    //a and l commands and directives have been checked to have their name as the second token
+    let name_span = match command {
+        Command::CCommand { .. } => span,
+        Command::ACommand {
+            symbol: Symbol::Expr(_),
+        } => lexer::span_of(&tokens[1..]).unwrap(),
+        _ => tokens[1].span,
+    };
+
+    Ok(Statement {
+        command,
+        span,
+        name_span,
+    })
+}
+
+fn parse_a_command(tokens: &[Token]) -> Result<Command, ParseError> {
+    let invalid =
+        || ParseError::InvalidCommand(lexer::text_of(tokens), lexer::span_of(tokens).unwrap());
+
+    let symbol = match &tokens[1..] {
+        [] => return Err(invalid()),
+
+        [token] => match literal(token, NumberUse::AInstruction) {
+            Some(value) => Symbol::Decimal(value?),
+            None if token.kind == TokenKind::Word => Symbol::Name(token.text.clone()),
+            None => return Err(invalid()),
+        },
+
+        operand => Symbol::Expr(expr::parse(operand)?),
+    };
+
+    Ok(Command::ACommand { symbol })
+}
+
+//the value of a decimal, hex, binary or char literal, none if the token isn't a number
+//used says what the number is for when it is too large
+fn literal(token: &Token, used: NumberUse) -> Option<Result<u16, ParseError>> {
+    if !lexer::is_number(token) {
+        return None;
     }
 
-    fn parse_l_command(&self, cmd: &str, line: u32) -> Result<Command, ParseError> {
-        //item inside () must be a string, cannot be a number
-        //since opening brace already checked, can check that there is a last brace, then take a slice of the middle
-        //not checking the validity of brackets, justs if they start with brace, end with brace, and have a symbol in middle
-        if let Some(c) = cmd.chars().last() {
-            if c != ')' {
-                return Err(ParseError::InvalidLabel(cmd.into(), line));
+    let value = match lexer::number_value(token) {
+        Some(v) if v <= 32767 => Ok(v as u16),
+        Some(_) => Err(ParseError::IntegerOverflow(
+            token.text.clone(),
+            used,
+            token.span,
+        )),
+        None => Err(ParseError::InvalidNumber(token.text.clone(), token.span)),
+    };
+
+    Some(value)
+}
+
+fn parse_l_command(tokens: &[Token]) -> Result<Command, ParseError> {
+    //item inside () must be a single symbol, which can't start with a digit
+    match tokens {
+        [_, label, close] if label.kind == TokenKind::Word && close.is_punct(')') => {
+            if !lexer::is_symbol(&label.text) {
+                return Err(ParseError::InvalidLabel(label.text.clone(), label.span));
             }
-        } else {
-            return Err(ParseError::InvalidLabel(cmd.into(), line));
+
+            Ok(Command::LCommand {
+                label: label.text.clone(),
+            })
         }
 
-        let label_contents = &cmd[1..cmd.len() - 1];
+        _ => Err(ParseError::InvalidLabel(
+            lexer::text_of(tokens),
+            lexer::span_of(tokens).unwrap(),
+        )),
+    }
+}
 
-        match label_contents.parse::<u16>() {
-            Ok(_) => return Err(ParseError::InvalidLabel(label_contents.into(), line)),
-            Err(_) => Ok(Command::LCommand {
-                label: label_contents.into(),
-            }),
-        }
+//.equ NAME value, .define NAME value, .alias NAME TARGET or a .var
+//macros, .data and .string are handled before parsing so they never get here
+fn parse_directive(tokens: &[Token]) -> Result<Command, ParseError> {
+    let invalid =
+        || ParseError::InvalidDirective(tokens[0].text.clone(), lexer::span_of(tokens).unwrap());
+
+    if tokens[0].text == ".var" {
+        return parse_var(tokens).unwrap_or_else(|| Err(invalid()));
     }
 
-    fn parse_c_command(&self, cmd: &str, line: u32) -> Result<Command, ParseError> {
-        //in form dest=comp;jmp
-        //where "dest=" and ";jmp" parts are optional
-        //if not a jump, then it must have dest= (i think)
-        let mut dest: Option<String> = None;
-        let mut jmp: Option<String> = None;
-        let mut comp = String::new();
+    let [directive, name, operand] = tokens else {
+        return Err(invalid());
+    };
 
-        let mut c_iter = cmd.chars();
+    if name.kind != TokenKind::Word || operand.kind != TokenKind::Word {
+        return Err(invalid());
+    }
 
-        if cmd.contains('=') {
-            //iterate until = and check if valid dest
-            let mut temp_dest = String::new();
+    //the name can't start with a digit, as with labels
+    if !lexer::is_symbol(&name.text) {
+        return Err(invalid());
+    }
 
-            loop {
-                match c_iter.next() {
-                    Some('=') | None => break,
-                    Some(c) => temp_dest.push(c),
-                }
-            }
+    match directive.text.as_str() {
+        ".equ" | ".define" => match literal(operand, NumberUse::Constant) {
+            Some(value) => Ok(Command::Constant {
+                name: name.text.clone(),
+                value: value?,
+            }),
 
-            let mut chars: Vec<char> = temp_dest.chars().collect();
-            chars.sort();
-            let temp_dest: String = chars.into_iter().collect();
+            None => Err(invalid()),
+        },
 
-            if !VALID_DESTS.contains(&temp_dest.as_str()) {
-                return Err(ParseError::InvalidDest(temp_dest, line));
-            } else {
-                dest = Some(temp_dest);
-            }
-        }
+        ".alias" if lexer::is_symbol(&operand.text) => Ok(Command::Alias {
+            name: name.text.clone(),
+            target: operand.text.clone(),
+        }),
 
-        //here we should be at the beginning of 'comp' part of instruction
-        let mut has_jmp = false;
-        loop {
-            match c_iter.next() {
-                Some(';') => {
-                    has_jmp = true;
-                    break;
-                }
-                Some(c) => comp.push(c),
-                None => break,
-            }
-        }
+        _ => Err(invalid()),
+    }
+}
 
-        if !VALID_COMPS.contains(&comp.as_str()) {
-            return Err(ParseError::InvalidCmp(comp, line));
-        }
+//.var NAME, .var NAME SIZE, .var NAME @ ADDR or .var NAME SIZE @ ADDR
+//none if it isn't written like any of them
+fn parse_var(tokens: &[Token]) -> Option<Result<Command, ParseError>> {
+    let [_, name, rest @ ..] = tokens else {
+        return None;
+    };
 
-        if has_jmp {
-            //iterator will already be at the ";", so can just collect the rest
-            let temp_jmp = c_iter.collect::<String>();
+    if !lexer::is_symbol(&name.text) {
+        return None;
+    }
 
-            if !VALID_JUMPS.contains(&temp_jmp.as_str()) {
-                return Err(ParseError::InvalidJmp(temp_jmp, line));
-            }
+    let (size, at) = match rest {
+        [] => (None, None),
+        [size] => (Some(size), None),
+        [sign, at] if sign.is_punct('@') => (None, Some(at)),
+        [size, sign, at] if sign.is_punct('@') => (Some(size), Some(at)),
+        _ => return None,
+    };
+
+    let size = match size {
+        Some(size) => match literal(size, NumberUse::VarSize)? {
+            Ok(0) => return None,
+            Ok(size) => size,
+            Err(e) => return Some(Err(e)),
+        },
+        None => 1,
+    };
+
+    let at = match at.map(|at| literal(at, NumberUse::VarAddress)) {
+        Some(Some(Ok(at))) => Some(at),
+        Some(Some(Err(e))) => return Some(Err(e)),
+        Some(None) => return None,
+        None => None,
+    };
+
+    Some(Ok(Command::Var {
+        name: name.text.clone(),
+        size,
+        at,
+    }))
+}
 
-            jmp = Some(temp_jmp);
+fn parse_c_command(tokens: &[Token]) -> Result<Command, ParseError> {
+    //in form dest=comp;jmp
+    //where "dest=" and ";jmp" parts are optional
+    let mut dest: Option<Dest> = None;
+    let mut jmp: Option<Jump> = None;
+    let mut rest = tokens;
+
+    if let Some(eq) = tokens.iter().position(|t| t.is_punct('=')) {
+        //check if the part before = is a valid dest, the order of the registers doesn't matter
+        //null is only how the encoding tables name a missing dest, it can't be written
+        let text = lexer::text_of(&tokens[..eq]);
+
+        match Dest::from_mnemonic(&text) {
+            Some(d) if d != Dest::Null => dest = Some(d),
+            _ => {
+                let span = lexer::span_of(&tokens[..eq]).unwrap_or(tokens[eq].span);
+                return Err(ParseError::InvalidDest(text, span));
+            }
         }
 
-        Ok(Command::CCommand { dest, comp, jmp })
+        rest = &tokens[eq + 1..];
     }
+
+    //split what's left into comp and jump
+    let (comp_tokens, jmp_tokens) = match rest.iter().position(|t| t.is_punct(';')) {
+        Some(semi) => (&rest[..semi], Some((&rest[semi], &rest[semi + 1..]))),
+        None => (rest, None),
+    };
+
+    let comp_text = lexer::text_of(comp_tokens);
+    let Some(comp) = Comp::from_mnemonic(&comp_text) else {
+        //an empty comp points just after the = or at the ;
+        let span = lexer::span_of(comp_tokens).unwrap_or_else(|| {
+            let after = tokens.last().unwrap().span;
+            jmp_tokens.map_or(after.after(), |(semi, _)| semi.span)
+        });
+        return Err(ParseError::InvalidCmp(comp_text, span));
+    };
+
+    if let Some((semi, jmp_tokens)) = jmp_tokens {
+        let text = lexer::text_of(jmp_tokens);
+
+        match Jump::from_mnemonic(&text) {
+            Some(j) if j != Jump::Null => jmp = Some(j),
+            _ => {
+                let span = lexer::span_of(jmp_tokens).unwrap_or(semi.span.after());
+                return Err(ParseError::InvalidJmp(text, span));
+            }
+        }
+    }
+
+    Ok(Command::CCommand { dest, comp, jmp })
 }
 
 #[cfg(test)]
+#[allow(clippy::single_match)]
 mod test {
 
     use super::*;
@@ -243,7 +552,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -256,7 +565,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -269,7 +578,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -282,7 +591,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -292,8 +601,8 @@ mod test {
 
         match command {
             Command::CCommand { dest, comp, jmp } => {
-                assert_eq!(dest.as_ref().unwrap(), "M");
-                assert_eq!(comp, "D+M");
+                assert_eq!(*dest, Some(Dest::M));
+                assert_eq!(*comp, Comp::DPlusM);
                 assert!(jmp.is_none())
             }
 
@@ -307,7 +616,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -317,9 +626,9 @@ mod test {
 
         match command {
             Command::CCommand { dest, comp, jmp } => {
-                assert_eq!(dest.as_ref().unwrap(), "M");
-                assert_eq!(comp, "D+M");
-                assert_eq!(jmp.as_ref().unwrap(), "JMP")
+                assert_eq!(*dest, Some(Dest::M));
+                assert_eq!(*comp, Comp::DPlusM);
+                assert_eq!(*jmp, Some(Jump::JMP))
             }
 
             _ => {}
@@ -331,7 +640,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -342,8 +651,8 @@ mod test {
         match command {
             Command::CCommand { dest, comp, jmp } => {
                 assert!(dest.is_none());
-                assert_eq!(comp, "0");
-                assert_eq!(jmp.as_ref().unwrap(), "JMP")
+                assert_eq!(*comp, Comp::Zero);
+                assert_eq!(*jmp, Some(Jump::JMP))
             }
 
             _ => {}
@@ -356,7 +665,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -369,7 +678,7 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
@@ -382,10 +691,219 @@ mod test {
         let bytes = label.as_bytes();
         let reader = BufReader::new(bytes);
 
-        let mut p = Parser::new(reader);
+        let mut p = Parser::new(reader).unwrap();
 
         let res = p.advance();
 
         assert!(res.is_err());
     }
+
+    #[test]
+    fn whitespace_is_ignored() {
+        let src = "A M=D\n0;J MP\nD=D + 1\nAM = D ; JGT\n";
+        let (statements, errors) = Parser::new(BufReader::new(src.as_bytes()))
+            .unwrap()
+            .parse_all();
+
+        assert!(errors.is_empty(), "{errors:?}");
+        let texts: Vec<String> = statements.iter().map(|s| s.command.to_string()).collect();
+        assert_eq!(texts, vec!["AM=D", "0;JMP", "D=D+1", "AM=D;JGT"]);
+    }
+
+    #[test]
+    fn cmp_span_skips_comments() {
+        let src = "// hi\n\n  D = M+X\n";
+        let mut p = Parser::new(BufReader::new(src.as_bytes())).unwrap();
+
+        assert!(p.has_more_commands());
+
+        match p.advance() {
+            Err(ParseError::InvalidCmp(c, span)) => {
+                assert_eq!(c, "M+X");
+                assert_eq!(span, Span::new(3, 7, 3));
+            }
+            r => panic!("expected invalid comp, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn jmp_span_counts_lines() {
+        let src = "@1\nD=A\n0;JXX";
+        let mut p = Parser::new(BufReader::new(src.as_bytes())).unwrap();
+
+        let mut errors = Vec::new();
+        while p.has_more_commands() {
+            if let Err(e) = p.advance() {
+                errors.push(e);
+            }
+        }
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span(), Span::new(3, 3, 3));
+    }
+
+    #[test]
+    fn invalid_command_span() {
+        let src = "  xyz";
+        let mut p = Parser::new(BufReader::new(src.as_bytes())).unwrap();
+
+        match p.advance() {
+            Err(ParseError::InvalidCommand(c, span)) => {
+                assert_eq!(c, "xyz");
+                assert_eq!(span, Span::new(1, 3, 3));
+            }
+            r => panic!("expected invalid command, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn end_of_file_span() {
+        let mut p = Parser::new(BufReader::new("".as_bytes())).unwrap();
+
+        match p.advance() {
+            Err(ParseError::EndOfFile(span)) => assert_eq!(span, Span::new(1, 1, 0)),
+            r => panic!("expected end of file, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn trailing_comment_and_leading_minus() {
+        let src = "\tD=M // load\r\n-1;JMP /* loop */\r\n";
+        let mut p = Parser::new(BufReader::new(src.as_bytes())).unwrap();
+
+        assert!(p.advance().is_ok());
+        assert!(matches!(p.get_current(), Some(Command::CCommand { comp: Comp::M, .. })));
+
+        assert!(p.advance().is_ok());
+        assert!(matches!(p.get_current(), Some(Command::CCommand { comp: Comp::NegOne, .. })));
+
+        assert!(!p.has_more_commands());
+    }
+
+    #[test]
+    fn unterminated_comment() {
+        let src = "@1\n/* open";
+        let mut p = Parser::new(BufReader::new(src.as_bytes())).unwrap();
+
+        match p.advance() {
+            Err(ParseError::UnterminatedComment(span)) => assert_eq!(span, Span::new(2, 1, 2)),
+            r => panic!("expected unterminated comment, got {r:?}"),
+        }
+
+        assert!(p.advance().is_ok());
+    }
+
+    #[test]
+    fn parse_all_spans() {
+        let src = "// header\n  @i\n(LOOP)\nD=X\n  M = D ; JMP\n";
+        let (statements, errors) = Parser::new(BufReader::new(src.as_bytes()))
+            .unwrap()
+            .parse_all();
+
+        assert_eq!(statements.len(), 3);
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(statements[0].span, Span::new(2, 3, 2));
+        assert_eq!(statements[0].name_span, Span::new(2, 4, 1));
+        assert_eq!(statements[1].name_span, Span::new(3, 2, 4));
+        assert_eq!(statements[2].span, Span::new(5, 3, 11));
+    }
+
+    #[test]
+    fn directives() {
+        let src = ".equ ROW 32\n.define BIG 40000\n.alias i R13\n.org 5\n";
+        let (statements, errors) = Parser::new(BufReader::new(src.as_bytes()))
+            .unwrap()
+            .parse_all();
+
+        assert!(matches!(
+            &statements[0].command,
+            Command::Constant { name, value: 32 } if name == "ROW"
+        ));
+        assert_eq!(statements[0].name_span, Span::new(1, 6, 3));
+        assert_eq!(statements[1].command.to_string(), ".alias i R13");
+
+        match errors.as_slice() {
+            [
+                ParseError::IntegerOverflow(_, NumberUse::Constant, _),
+                ParseError::InvalidDirective(d, _),
+            ] => assert_eq!(d, ".org"),
+            r => panic!("expected directive errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn names_starting_with_a_digit() {
+        let src = "(1abc)\n.equ 1x 5\n.alias 9z R13\n.alias r 9z\n.var 2v\n(abc1)\n";
+        let (statements, errors) = Parser::new(BufReader::new(src.as_bytes()))
+            .unwrap()
+            .parse_all();
+
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].command.to_string(), "(abc1)");
+
+        match errors.as_slice() {
+            [ParseError::InvalidLabel(label, span), rest @ ..] => {
+                assert_eq!(label, "1abc");
+                assert_eq!(*span, Span::new(1, 2, 4));
+                assert_eq!(rest.len(), 4);
+                assert!(
+                    rest.iter()
+                        .all(|e| matches!(e, ParseError::InvalidDirective(..)))
+                );
+            }
+            r => panic!("expected name errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn a_literals_and_expressions() {
+        let src = "@0x4000\n@0b101\n@'A'\n@SCREEN+32*5\n@0x10000\n@0xZZ\n@KBD-\n";
+        let (statements, errors) = Parser::new(BufReader::new(src.as_bytes()))
+            .unwrap()
+            .parse_all();
+
+        let values: Vec<String> = statements.iter().map(|s| s.command.to_string()).collect();
+        assert_eq!(values, vec!["@16384", "@5", "@65", "@SCREEN+32*5"]);
+        assert_eq!(statements[3].name_span, Span::new(4, 2, 11));
+
+        match errors.as_slice() {
+            [
+                ParseError::IntegerOverflow(big, NumberUse::AInstruction, _),
+                ParseError::InvalidNumber(bad, _),
+                ParseError::InvalidExpression(e, span),
+            ] => {
+                assert_eq!(big, "0x10000");
+                assert_eq!(bad, "0xZZ");
+                assert_eq!(e, "KBD-");
+                assert_eq!(*span, Span::new(7, 6, 0));
+            }
+            r => panic!("expected number errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn var_directives() {
+        let src = ".var x\n.var buf 64\n.var io @ 0x100\n.var b 2 @ 100\n.var z 0\n.var q @\n\
+                   .var big 40000\n.var far @ 40000\n";
+        let (statements, errors) = Parser::new(BufReader::new(src.as_bytes()))
+            .unwrap()
+            .parse_all();
+
+        let texts: Vec<String> = statements.iter().map(|s| s.command.to_string()).collect();
+        assert_eq!(
+            texts,
+            vec![".var x", ".var buf 64", ".var io @ 256", ".var b 2 @ 100"]
+        );
+        assert_eq!(statements[1].name_span, Span::new(2, 6, 3));
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                ParseError::InvalidDirective(..),
+                ParseError::InvalidDirective(..),
+                ParseError::IntegerOverflow(_, NumberUse::VarSize, _),
+                ParseError::IntegerOverflow(_, NumberUse::VarAddress, _),
+            ]
+        ));
+    }
 }
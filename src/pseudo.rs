@@ -0,0 +1,351 @@
+use crate::code::Code;
+use crate::expr;
+use crate::instruction::Dest;
+use crate::lexer::{self, Token, TokenKind};
+use crate::parser::ParseError;
+use crate::span::Span;
+
+//built-in pseudo-instructions, each one is replaced by the real instructions it stands for
+//
+//  @-1, @40000         a constant too big for an a-instruction, @N then A=-A or A=!A
+//  goto LABEL          @LABEL, 0;JMP
+//  if D>0 goto LABEL   @LABEL, D;JGT, any computation without A or M can be compared with 0
+//                      using <, <=, >, >=, == or !=
+//  D=[addr]            @addr, D=M, any destination can be used
+//  [addr]=D            @addr, M=D, any computation without A or M can be stored
+//
+//done after macros so a macro body can use them, and before parsing so the assembler only ever
+//sees real instructions. like a macro expansion the new tokens point back at the
+//pseudo-instruction, so the listing shows each instruction it became under it
+pub fn expand(lines: Vec<Vec<Token>>) -> (Vec<Vec<Token>>, Vec<ParseError>) {
+    let mut expanded = Vec::new();
+    let mut errors = Vec::new();
+
+    for line in lines {
+        match expand_line(&line) {
+            Ok(Some(instructions)) => expanded.extend(instructions),
+            Ok(None) => expanded.push(line),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    (expanded, errors)
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    token.kind == TokenKind::Word && token.text == keyword
+}
+
+//the instructions for a pseudo-instruction, none for a line that isn't one
+fn expand_line(line: &[Token]) -> Result<Option<Vec<Vec<Token>>>, ParseError> {
+    let call = lexer::span_of(line).unwrap();
+    let first = &line[0];
+
+    if first.is_punct('@') {
+        let operand = &line[1..];
+        return match big_constant(operand) {
+            Some(_) => load(first.span, operand, call).map(Some),
+            None => Ok(None),
+        };
+    }
+
+    if is_keyword(first, "goto") {
+        return goto(line, call).map(Some);
+    }
+
+    if is_keyword(first, "if") {
+        return if_goto(line, call).map(Some);
+    }
+
+    if line.iter().any(|t| t.is_punct('[')) {
+        return memory(line, call).map(Some);
+    }
+
+    Ok(None)
+}
+
+//goto LABEL
+fn goto(line: &[Token], call: Span) -> Result<Vec<Vec<Token>>, ParseError> {
+    let target = &line[1..];
+    if target.is_empty() {
+        return Err(ParseError::InvalidPseudo(line[0].text.clone(), call));
+    }
+
+    let mut lines = load(line[0].span, target, call)?;
+    lines.push(c_instruction(None, "0", Some("JMP"), call, call)?);
+    Ok(lines)
+}
+
+//if COMP OP 0 goto LABEL
+fn if_goto(line: &[Token], call: Span) -> Result<Vec<Vec<Token>>, ParseError> {
+    let invalid = || ParseError::InvalidPseudo(line[0].text.clone(), call);
+
+    let g = line
+        .iter()
+        .position(|t| is_keyword(t, "goto"))
+        .ok_or_else(invalid)?;
+    let (condition, target) = (&line[1..g], &line[g + 1..]);
+
+    let [compared @ .., zero] = condition else {
+        return Err(invalid());
+    };
+    if target.is_empty() || lexer::number_value(zero) != Some(0) {
+        return Err(invalid());
+    }
+
+    //comparisons are one or two punctuation tokens, a computation never ends with one
+    let op_len = compared
+        .iter()
+        .rev()
+        .take(2)
+        .take_while(|t| matches!(t.kind, TokenKind::Punct('<' | '>' | '=' | '!')))
+        .count();
+    let (comp, op) = compared.split_at(compared.len() - op_len);
+
+    let jump = match lexer::text_of(op).as_str() {
+        ">" => "JGT",
+        ">=" => "JGE",
+        "<" => "JLT",
+        "<=" => "JLE",
+        "==" => "JEQ",
+        "!=" => "JNE",
+        _ => return Err(invalid()),
+    };
+
+    let comp_span = lexer::span_of(comp).ok_or_else(invalid)?;
+    let comp = unclobbered(comp, comp_span)?;
+
+    let mut lines = load(line[g].span, target, call)?;
+    lines.push(c_instruction(None, &comp, Some(jump), comp_span, call)?);
+    Ok(lines)
+}
+
+//DEST=[addr] or [addr]=COMP
+fn memory(line: &[Token], call: Span) -> Result<Vec<Vec<Token>>, ParseError> {
+    let invalid = || ParseError::InvalidPseudo("[".into(), call);
+
+    if line[0].is_punct('[') {
+        let close = line
+            .iter()
+            .position(|t| t.is_punct(']'))
+            .ok_or_else(invalid)?;
+        let addr = &line[1..close];
+        let comp = match &line[close + 1..] {
+            [eq, comp @ ..] if eq.is_punct('=') && !comp.is_empty() => comp,
+            _ => return Err(invalid()),
+        };
+        if addr.is_empty() || comp.iter().any(|t| t.is_punct('[') || t.is_punct(']')) {
+            return Err(invalid());
+        }
+
+        let comp_span = lexer::span_of(comp).unwrap();
+        let text = unclobbered(comp, comp_span)?;
+
+        let mut lines = load(line[0].span, addr, call)?;
+        lines.push(c_instruction(Some(Dest::M), &text, None, comp_span, call)?);
+        return Ok(lines);
+    }
+
+    let eq = line
+        .iter()
+        .position(|t| t.is_punct('='))
+        .ok_or_else(invalid)?;
+    let (dest, addr) = match &line[eq + 1..] {
+        [open, addr @ .., close] if open.is_punct('[') && close.is_punct(']') => {
+            (&line[..eq], addr)
+        }
+        _ => return Err(invalid()),
+    };
+    if addr.is_empty() || addr.iter().any(|t| t.is_punct('[') || t.is_punct(']')) {
+        return Err(invalid());
+    }
+
+    let text = lexer::text_of(dest);
+    let span = lexer::span_of(dest).unwrap_or(line[eq].span);
+    let dest = Dest::from_mnemonic(&text)
+        .filter(|d| *d != Dest::Null)
+        .ok_or(ParseError::InvalidDest(text, span))?;
+
+    let mut lines = load(line[eq + 1].span, addr, call)?;
+    lines.push(c_instruction(Some(dest), "M", None, call, call)?);
+    Ok(lines)
+}
+
+//the text of a computation that can run after A has been loaded with an address,
+//so one that doesn't read A or M
+fn unclobbered(comp: &[Token], span: Span) -> Result<String, ParseError> {
+    let text = lexer::text_of(comp);
+
+    if text.contains(['A', 'M']) {
+        return Err(ParseError::ClobberedComp(text, span));
+    }
+
+    Ok(text)
+}
+
+//a constant that fits in a word but not in an a-instruction, as the number to load and the
+//computation that turns it into the constant
+//only plain numbers are looked at, an expression with names is worked out after addresses are
+fn big_constant(operand: &[Token]) -> Option<(u16, &'static str)> {
+    let e = expr::parse(operand).ok().filter(|e| e.names().is_empty())?;
+
+    match e.eval(&|_| None).ok()? {
+        0..=32767 => None,
+        v @ -32767..=-1 => Some(((-v) as u16, "-A")),
+        v @ (-32768 | 32768..=65535) => Some((!(v as u16), "!A")),
+        _ => None,
+    }
+}
+
+//the instructions putting the operand in A, at is where the @ would be written
+fn load(at: Span, operand: &[Token], call: Span) -> Result<Vec<Vec<Token>>, ParseError> {
+    let at = Token {
+        kind: TokenKind::Punct('@'),
+        text: "@".into(),
+        span: at.expanded_from(call),
+    };
+
+    let Some((n, comp)) = big_constant(operand) else {
+        let mut line = vec![at];
+        line.extend(operand.iter().map(|t| Token {
+            span: t.span.expanded_from(call),
+            ..t.clone()
+        }));
+        return Ok(vec![line]);
+    };
+
+    let number = Token {
+        kind: TokenKind::Word,
+        text: n.to_string(),
+        span: lexer::span_of(operand).unwrap().expanded_from(call),
+    };
+
+    Ok(vec![
+        vec![at, number],
+        c_instruction(Some(Dest::A), comp, None, call, call)?,
+    ])
+}
+
+//the tokens of a c-instruction spanning the whole pseudo-instruction
+//the computation is looked up in the encoding table first, so an expansion is always something
+//that assembles, span is where a bad computation was written
+fn c_instruction(
+    dest: Option<Dest>,
+    comp: &str,
+    jump: Option<&str>,
+    span: Span,
+    call: Span,
+) -> Result<Vec<Token>, ParseError> {
+    if Code::comp(comp).is_none() {
+        return Err(ParseError::InvalidCmp(comp.into(), span));
+    }
+
+    let mut text = String::new();
+    if let Some(d) = dest {
+        text += &format!("{}=", d.mnemonic());
+    }
+    text += comp;
+    if let Some(j) = jump {
+        text += &format!(";{j}");
+    }
+
+    let span = call.expanded_from(call);
+    Ok(lexer::tokenize(&text)
+        .lines
+        .remove(0)
+        .into_iter()
+        .map(|t| Token { span, ..t })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_source(src: &str) -> (Vec<String>, Vec<Vec<Token>>, Vec<ParseError>) {
+        let (lines, errors) = expand(lexer::tokenize(src).lines);
+        let texts = lines.iter().map(|l| lexer::text_of(l)).collect();
+
+        (texts, lines, errors)
+    }
+
+    #[test]
+    fn expansions() {
+        let src = "@-1\n@40000\n@-0x8000\n@5\ngoto LOOP\nif D>0 goto END\n\
+                   if D-1 != 0 goto -1\nD=[SP]\nMD=[SCREEN+32]\n[R13]=D\n[x]=-1\n";
+        let (texts, _, errors) = expand_source(src);
+
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            texts,
+            vec![
+                "@1",
+                "A=-A",
+                "@25535",
+                "A=!A",
+                "@32767",
+                "A=!A",
+                "@5",
+                "@LOOP",
+                "0;JMP",
+                "@END",
+                "D;JGT",
+                "@1",
+                "A=-A",
+                "D-1;JNE",
+                "@SP",
+                "D=M",
+                "@SCREEN+32",
+                "DM=M",
+                "@R13",
+                "M=D",
+                "@x",
+                "M=-1",
+            ]
+        );
+    }
+
+    #[test]
+    fn expansions_point_at_the_pseudo_instruction() {
+        let (_, lines, _) = expand_source("@5\n  goto LOOP\n");
+
+        //lines that aren't pseudo-instructions are left alone
+        assert_eq!(lines[0][0].span, Span::new(1, 1, 1));
+
+        //the label keeps its place, the rest covers the whole line
+        let call = Span::new(2, 3, 9);
+        assert_eq!(lines[1][1].span, Span::new(2, 8, 4).expanded_from(call));
+        assert_eq!(lines[2][0].span, call.expanded_from(call));
+        assert_eq!(lexer::span_of(&lines[1]).unwrap().col, 3);
+    }
+
+    #[test]
+    fn errors() {
+        let src = "goto\nif D>1 goto X\nif D goto X\n[R13]=M+1\nAX=[R13]\n[R13=D\nif A<0 goto X\n";
+        let (texts, _, errors) = expand_source(src);
+
+        assert!(texts.is_empty());
+        match errors.as_slice() {
+            [
+                ParseError::InvalidPseudo(goto, _),
+                ParseError::InvalidPseudo(if1, _),
+                ParseError::InvalidPseudo(if2, _),
+                ParseError::ClobberedComp(stored, stored_span),
+                ParseError::InvalidDest(dest, _),
+                ParseError::InvalidPseudo(bracket, _),
+                ParseError::ClobberedComp(compared, _),
+            ] => {
+                assert_eq!(
+                    (goto.as_str(), if1.as_str(), if2.as_str()),
+                    ("goto", "if", "if")
+                );
+                assert_eq!(stored, "M+1");
+                assert_eq!(*stored_span, Span::new(4, 7, 3));
+                assert_eq!(dest, "AX");
+                assert_eq!(bracket, "[");
+                assert_eq!(compared, "A");
+            }
+            r => panic!("expected pseudo-instruction errors, got {r:?}"),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use crate::span::Span;
+
+//a file of assembly, the main source or one pulled in with .include
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    //how the file is named in diagnostics
+    pub name: String,
+    pub text: String,
+    //the file it was read from, none for a source given as a string
+    pub path: Option<PathBuf>,
+    //the .include that read this file, none for the main source
+    pub included_from: Option<Span>,
+}
+
+//every file read while assembling, indexed by Span::file
+#[derive(Debug, Clone)]
+pub struct Sources {
+    files: Vec<SourceFile>,
+}
+
+impl Sources {
+    pub fn new(name: impl Into<String>, text: impl Into<String>, path: Option<PathBuf>) -> Self {
+        Sources {
+            files: vec![SourceFile {
+                name: name.into(),
+                text: text.into(),
+                path,
+                included_from: None,
+            }],
+        }
+    }
+
+    //adds a file and gives the index its spans should use
+    pub fn add(&mut self, file: SourceFile) -> u32 {
+        self.files.push(file);
+        (self.files.len() - 1) as u32
+    }
+
+    pub fn get(&self, file: u32) -> Option<&SourceFile> {
+        self.files.get(file as usize)
+    }
+
+    pub fn main(&self) -> &SourceFile {
+        &self.files[0]
+    }
+
+    pub fn name(&self, file: u32) -> &str {
+        self.get(file).map_or("", |f| f.name.as_str())
+    }
+
+    pub fn text(&self, file: u32) -> &str {
+        self.get(file).map_or("", |f| f.text.as_str())
+    }
+
+    //the .include lines that led to a file, innermost first, empty for the main source
+    pub fn include_chain(&self, file: u32) -> Vec<Span> {
+        let mut chain = Vec::new();
+        let mut file = file;
+
+        while let Some(span) = self.get(file).and_then(|f| f.included_from) {
+            chain.push(span);
+            file = span.file;
+        }
+
+        chain
+    }
+
+    //the place in the main source a span came from, itself or the outermost .include
+    pub fn in_main(&self, span: Span) -> Span {
+        self.include_chain(span.file)
+            .last()
+            .copied()
+            .unwrap_or(span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn include_chain() {
+        let mut sources = Sources::new("main.asm", ".include \"a.asm\"\n", None);
+        let a = sources.add(SourceFile {
+            name: "a.asm".into(),
+            text: "@1\n.include \"b.asm\"\n".into(),
+            path: None,
+            included_from: Some(Span::new(1, 1, 16)),
+        });
+        let b = sources.add(SourceFile {
+            name: "b.asm".into(),
+            text: "@2\n".into(),
+            path: None,
+            included_from: Some(Span::new(2, 1, 16).in_file(a)),
+        });
+
+        assert_eq!(
+            sources.include_chain(b),
+            vec![Span::new(2, 1, 16).in_file(a), Span::new(1, 1, 16)]
+        );
+        assert_eq!(
+            sources.in_main(Span::new(1, 2, 1).in_file(b)),
+            Span::new(1, 1, 16)
+        );
+        assert_eq!(sources.in_main(Span::new(3, 1, 1)), Span::new(3, 1, 1));
+        assert_eq!(sources.name(b), "b.asm");
+    }
+}
@@ -0,0 +1,74 @@
+//location of some text in a source file
+//line and col start at 1, col and len are counted in chars
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    //index of the file in source::Sources, 0 is the main source and the rest were included
+    pub file: u32,
+    pub line: u32,
+    pub col: u32,
+    pub len: u32,
+    //for code that came from a macro, where the macro was called
+    //the span itself is then inside the macro body
+    pub call_site: Option<CallSite>,
+}
+
+//the place of a macro call, the outermost one when calls are nested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallSite {
+    pub file: u32,
+    pub line: u32,
+    pub col: u32,
+    pub len: u32,
+}
+
+impl Span {
+    pub fn new(line: u32, col: u32, len: u32) -> Self {
+        Span {
+            file: 0,
+            line,
+            col,
+            len,
+            call_site: None,
+        }
+    }
+
+    //the same place in another file
+    pub fn in_file(self, file: u32) -> Span {
+        Span { file, ..self }
+    }
+
+    //one past the last char covered by the span
+    pub fn end_col(&self) -> u32 {
+        self.col + self.len
+    }
+
+    //an empty span just past the end of this one
+    pub fn after(&self) -> Span {
+        Span {
+            col: self.end_col(),
+            len: 0,
+            ..*self
+        }
+    }
+
+    //this span as part of the expansion of the macro called at call
+    pub fn expanded_from(self, call: Span) -> Span {
+        let call_site = call.call_site.unwrap_or(CallSite {
+            file: call.file,
+            line: call.line,
+            col: call.col,
+            len: call.len,
+        });
+
+        Span {
+            call_site: Some(call_site),
+            ..self
+        }
+    }
+}
+
+impl CallSite {
+    pub fn span(&self) -> Span {
+        Span::new(self.line, self.col, self.len).in_file(self.file)
+    }
+}
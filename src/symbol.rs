@@ -1,101 +1,847 @@
-use std::collections::HashMap;
-use std::io::{BufRead, Seek};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug)]
+use crate::Options;
+use crate::expr::{EvalError, Expr};
+use crate::instruction::MAX_A_VALUE;
+use crate::parser::{Command, Statement, Symbol};
+use crate::span::Span;
+
+//the default last address for variables, the word below the screen memory map
+pub const MAX_VARIABLE_ADDR: u16 = 16383;
+
+#[derive(Debug, Clone)]
 pub enum SymbolError {
-    InvalidSymbol,
-    OutofBoundsAddress,
-}
-
-pub fn create_table<R: BufRead + Seek>(
-    reader: &mut R,
-) -> Result<HashMap<String, String>, SymbolError> {
-    //dont need to do too much parsing here, just as little as possible
-    //first pass for labels then pass for variables
-    let mut table = HashMap::new();
-
-    //insert the predefined symbols
-    table.insert("R0".into(), format!("{:016b}", 0));
-    table.insert("R1".into(), format!("{:016b}", 1));
-    table.insert("R2".into(), format!("{:016b}", 2));
-    table.insert("R3".into(), format!("{:016b}", 3));
-    table.insert("R4".into(), format!("{:016b}", 4));
-    table.insert("R5".into(), format!("{:016b}", 5));
-    table.insert("R6".into(), format!("{:016b}", 6));
-    table.insert("R7".into(), format!("{:016b}", 7));
-    table.insert("R8".into(), format!("{:016b}", 8));
-    table.insert("R9".into(), format!("{:016b}", 9));
-    table.insert("R10".into(), format!("{:016b}", 10));
-    table.insert("R11".into(), format!("{:016b}", 11));
-    table.insert("R12".into(), format!("{:016b}", 12));
-    table.insert("R13".into(), format!("{:016b}", 13));
-    table.insert("R14".into(), format!("{:016b}", 14));
-    table.insert("R15".into(), format!("{:016b}", 15));
-    table.insert("SCREEN".into(), format!("{:016b}", 16384));
-    table.insert("KBD".into(), format!("{:016b}", 24576));
-    table.insert("SP".into(), format!("{:016b}", 0));
-    table.insert("LCL".into(), format!("{:016b}", 1));
-    table.insert("ARG".into(), format!("{:016b}", 2));
-    table.insert("THIS".into(), format!("{:016b}", 3));
-    table.insert("THAT".into(), format!("{:016b}", 4));
-
-    let mut lines: Vec<String> = reader
-        .lines()
-        .map(|line| line.map_err(|_| SymbolError::InvalidSymbol)) // Map errors to your custom error type
-        .collect::<Result<Vec<_>, _>>()?;
-
-    //rewind reader to reuse later
-    reader.rewind().unwrap();
-
-    lines.retain(|s| !s.trim().is_empty() && !s.starts_with("//"));
-
-    let mut command_index: u16 = 0;
-
-    for l in lines.iter() {
-        let mut s_iter = l.chars();
-
-        match s_iter.next() {
-            Some('(') => {
-                let symbol = s_iter.take_while(|&c| c != ')').collect::<String>();
-
-                //labels are removed from compiled code so their current index points to the next command
-                let mem_str = format!("{:016b}", command_index);
-                table.insert(symbol, mem_str);
-            }
-            Some(_) => {
-                command_index += 1;
-            }
-            None => {}
-        };
+    InvalidSymbol(String, Span),
+    //a variable that doesn't fit: the name, the variable base and the last address, and its use
+    OutofBoundsAddress(String, u16, u16, Span),
+    //a name that isn't a label or predefined symbol, only an error in strict mode
+    UnresolvedSymbol(String, Span),
+    //a name in an expression that isn't a label, constant, alias or .var, which would otherwise
+    //become a one word variable
+    UnresolvedInExpression(String, Span),
+    //a label, constant, alias or .var defined twice: the name, the second definition and the first one
+    DuplicateSymbol(String, Span, Span),
+    //a label, constant, alias or .var named like R0 or SCREEN
+    RedefinedPredefined(String, Span),
+    //a label, constant, alias or .var with the name of a -D definition
+    RedefinedDefine(String, Span),
+    //an alias to a name that isn't defined, the name and the .alias
+    UndefinedAlias(String, Span),
+    //an expression whose value doesn't fit in an a-instruction, the value and the expression
+    OutOfRange(i64, Span),
+    DivisionByZero(Span),
+    //a .var at a fixed address sharing memory with another: the name, its .var and the other one
+    VariableOverlap(String, Span, Span),
+    //a .var at a fixed address that runs past the last address for variables, which is given
+    ReservedMemory(String, u16, Span),
+    //a label too far into the program for an a-instruction to load, the name and its address
+    LabelOutOfRange(String, usize, Span),
+}
+
+impl SymbolError {
+    pub fn span(&self) -> Span {
+        match self {
+            SymbolError::InvalidSymbol(_, s)
+            | SymbolError::OutofBoundsAddress(_, _, _, s)
+            | SymbolError::UnresolvedSymbol(_, s)
+            | SymbolError::UnresolvedInExpression(_, s)
+            | SymbolError::DuplicateSymbol(_, s, _)
+            | SymbolError::RedefinedPredefined(_, s)
+            | SymbolError::RedefinedDefine(_, s)
+            | SymbolError::UndefinedAlias(_, s)
+            | SymbolError::OutOfRange(_, s)
+            | SymbolError::DivisionByZero(s)
+            | SymbolError::VariableOverlap(_, s, _)
+            | SymbolError::ReservedMemory(_, _, s)
+            | SymbolError::LabelOutOfRange(_, _, s) => *s,
+        }
+    }
+}
+
+//something that assembles but is probably a mistake
+#[derive(Debug, Clone)]
+pub enum SymbolWarning {
+    //the name, the @name followed by a memory access, and the label definition
+    LabelAsData(String, Span, Span),
+}
+
+impl SymbolWarning {
+    pub fn span(&self) -> Span {
+        match self {
+            SymbolWarning::LabelAsData(_, s, _) => *s,
+        }
+    }
+}
+
+//what to do with an @name that isn't a label or predefined symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymbolMode {
+    //give it the next free ram address from 16, as the hack spec does
+    #[default]
+    AutoVariables,
+    //report it as an UnresolvedSymbol, catches typos in label names
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    //given before the source is read, R0-R15, SCREEN and so on
+    Predefined,
+    //a (LABEL), its address is in rom
+    Label,
+    //an @name given the next free ram address, or a .var
+    Variable,
+    //a .equ or .define, just a number
+    Constant,
+    //a .alias, has the address of the symbol it names
+    Alias,
+}
+
+impl SymbolKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SymbolKind::Predefined => "predefined",
+            SymbolKind::Label => "label",
+            SymbolKind::Variable => "variable",
+            SymbolKind::Constant => "constant",
+            SymbolKind::Alias => "alias",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolEntry {
+    pub name: String,
+    pub address: u16,
+    pub kind: SymbolKind,
+    //where the label or first use of the variable is, predefined symbols have no source
+    pub span: Option<Span>,
+}
+
+//symbol names to addresses, remembering the order they were defined in
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    entries: Vec<SymbolEntry>,
+    index: HashMap<String, usize>,
+}
+
+//the predefined symbols of the hack platform
+pub fn hack_predefined() -> Vec<(String, u16)> {
+    let mut symbols: Vec<(String, u16)> = (0..16).map(|i| (format!("R{i}"), i)).collect();
+
+    for (name, addr) in [
+        ("SCREEN", 16384),
+        ("KBD", 24576),
+        ("SP", 0),
+        ("LCL", 1),
+        ("ARG", 2),
+        ("THIS", 3),
+        ("THAT", 4),
+    ] {
+        symbols.push((name.into(), addr));
+    }
+
+    symbols
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //a table holding just the given predefined symbols
+    pub fn with_predefined(predefined: &[(String, u16)]) -> Self {
+        let mut table = Self::new();
+        for (name, addr) in predefined {
+            table.insert(name.clone(), *addr, SymbolKind::Predefined, None);
+        }
+        table
+    }
+
+    //adds a symbol, a name that is already defined keeps its first definition
+    //returns whether the symbol was added
+    pub fn insert(
+        &mut self,
+        name: String,
+        address: u16,
+        kind: SymbolKind,
+        span: Option<Span>,
+    ) -> bool {
+        if self.index.contains_key(&name) {
+            return false;
+        }
+
+        self.index.insert(name.clone(), self.entries.len());
+        self.entries.push(SymbolEntry {
+            name,
+            address,
+            kind,
+            span,
+        });
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SymbolEntry> {
+        self.index.get(name).map(|&i| &self.entries[i])
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.get(name).map(|e| e.address)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    //the value of the expression of an a-instruction, which has to be 0 to 32767
+    //span is the whole expression
+    pub fn evaluate(&self, expr: &Expr, span: Span) -> Result<u16, SymbolError> {
+        match expr.eval(&|name| self.address(name)) {
+            Ok(v) if (0..=i64::from(MAX_A_VALUE)).contains(&v) => Ok(v as u16),
+            Ok(v) => Err(SymbolError::OutOfRange(v, span)),
+            Err(EvalError::Undefined(name, s)) => Err(SymbolError::UnresolvedSymbol(name, s)),
+            Err(EvalError::DivisionByZero) => Err(SymbolError::DivisionByZero(span)),
+        }
+    }
+
+    //every symbol in the order it was defined, predefined symbols first
+    pub fn iter(&self) -> impl Iterator<Item = &SymbolEntry> {
+        self.entries.iter()
     }
 
-    //second pass to add variable, variables stored from mem address 1024
-    //if the symbol is a number, then this is an A-Instruction and gets ignored
-    let mut base_addr: u16 = 16;
-    for (_, l) in lines.iter().enumerate() {
-        let mut s_iter = l.chars();
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+//hands out ram for variables from the variable base, stepping over the ones placed with .var @
+struct Allocator {
+    next: u32,
+    //one past the last address that can be handed out
+    end: u32,
+    //the start and end (exclusive) of each placed variable
+    placed: Vec<(u32, u32)>,
+}
+
+impl Allocator {
+    //the address of size free words, none once they would go past the end
+    fn alloc(&mut self, size: u16) -> Option<u16> {
+        let mut start = self.next;
+
+        loop {
+            let end = start + u32::from(size);
+            if end > self.end {
+                return None;
+            }
+
+            match self.placed.iter().find(|(s, e)| start < *e && *s < end) {
+                Some((_, e)) => start = *e,
+                None => {
+                    self.next = end;
+                    return Some(start as u16);
+                }
+            }
+        }
+    }
+}
+
+//builds the table even when some symbols are bad, so every problem can be reported in one go
+pub fn create_table(
+    statements: &[Statement],
+    options: &Options,
+) -> Result<SymbolTable, Vec<SymbolError>> {
+    //first pass for labels and constants, then .vars, then the other variables, then aliases
+    let mut table = SymbolTable::with_predefined(&options.predefined);
+    let mut errors = Vec::new();
+
+    //counted wide, a program can be longer than any rom it fits in
+    let mut command_index: usize = 0;
+
+    for s in statements.iter() {
+        match &s.command {
+            //labels are removed from compiled code so their current index points to the next command
+            //one that can't be loaded is still added, so its uses aren't reported as well
+            Command::LCommand { label } => {
+                let address = match u16::try_from(command_index) {
+                    Ok(a) if a <= MAX_A_VALUE => a,
+                    _ => {
+                        errors.push(SymbolError::LabelOutOfRange(
+                            label.clone(),
+                            command_index,
+                            s.name_span,
+                        ));
+                        0
+                    }
+                };
+
+                define(
+                    &mut table,
+                    &mut errors,
+                    label,
+                    address,
+                    SymbolKind::Label,
+                    s,
+                );
+            }
+
+            Command::Constant { name, value } => {
+                define(
+                    &mut table,
+                    &mut errors,
+                    name,
+                    *value,
+                    SymbolKind::Constant,
+                    s,
+                );
+            }
+
+            c if c.is_instruction() => command_index += 1,
 
-        match s_iter.next() {
-            Some('@') => {
-                let symbol = s_iter.collect::<String>();
+            _ => {}
+        }
+    }
+
+    //variables placed at an address first, so the rest can be put around them
+    let mut placed: Vec<(u32, u32, Span)> = Vec::new();
+    for s in statements.iter() {
+        let Command::Var {
+            name,
+            size,
+            at: Some(at),
+        } = &s.command
+        else {
+            continue;
+        };
 
-                //failing to parse means it must be a string
-                match symbol.parse::<u16>() {
-                    Ok(_) => continue,
-                    Err(_) => {}
+        let (start, end) = (u32::from(*at), u32::from(*at) + u32::from(*size));
+        if end > u32::from(options.variable_limit) + 1 {
+            errors.push(SymbolError::ReservedMemory(
+                name.clone(),
+                options.variable_limit,
+                s.name_span,
+            ));
+            continue;
+        }
+
+        if let Some((.., other)) = placed.iter().find(|(s, e, _)| start < *e && *s < end) {
+            errors.push(SymbolError::VariableOverlap(
+                name.clone(),
+                s.name_span,
+                *other,
+            ));
+            continue;
+        }
+
+        if define(&mut table, &mut errors, name, *at, SymbolKind::Variable, s) {
+            placed.push((start, end, s.name_span));
+        }
+    }
+
+    let mut allocator = Allocator {
+        next: u32::from(options.variable_base),
+        end: u32::from(options.variable_limit) + 1,
+        placed: placed.iter().map(|(s, e, _)| (*s, *e)).collect(),
+    };
+
+    for s in statements.iter() {
+        if let Command::Var {
+            name,
+            size,
+            at: None,
+        } = &s.command
+        {
+            match allocator.alloc(*size) {
+                Some(address) => {
+                    define(
+                        &mut table,
+                        &mut errors,
+                        name,
+                        address,
+                        SymbolKind::Variable,
+                        s,
+                    );
                 }
+                None => errors.push(SymbolError::OutofBoundsAddress(
+                    name.clone(),
+                    options.variable_base,
+                    options.variable_limit,
+                    s.name_span,
+                )),
+            }
+        }
+    }
+
+    //an alias isn't a variable even when it's defined after its first use
+    let aliases: HashSet<&str> = statements
+        .iter()
+        .filter_map(|s| match &s.command {
+            Command::Alias { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    //second pass to add variables, stored from the variable base address
+    //only a plain @name makes one, a name with an offset like @table+3 has to be a label,
+    //constant or .var, there is nothing past a one word variable to point at
+    let mut implicit = HashSet::new();
+    for s in statements.iter() {
+        let Command::ACommand {
+            symbol: Symbol::Name(symbol),
+        } = &s.command
+        else {
+            continue;
+        };
+
+        if table.contains(symbol) || aliases.contains(symbol.as_str()) {
+            continue;
+        }
+
+        if options.symbol_mode == SymbolMode::Strict {
+            errors.push(SymbolError::UnresolvedSymbol(symbol.clone(), s.name_span));
+            continue;
+        }
+
+        match allocator.alloc(1) {
+            Some(address) => {
+                table.insert(
+                    symbol.clone(),
+                    address,
+                    SymbolKind::Variable,
+                    Some(s.name_span),
+                );
+                implicit.insert(symbol.as_str());
+            }
+            None => errors.push(SymbolError::OutofBoundsAddress(
+                symbol.clone(),
+                options.variable_base,
+                options.variable_limit,
+                s.name_span,
+            )),
+        }
+    }
+
+    //aliases can name any symbol, including the variables just added, or earlier aliases
+    for s in statements.iter() {
+        let Command::Alias { name, target } = &s.command else {
+            continue;
+        };
+
+        match table.address(target) {
+            Some(address) => {
+                define(&mut table, &mut errors, name, address, SymbolKind::Alias, s);
+            }
+            None => errors.push(SymbolError::UndefinedAlias(target.clone(), s.span)),
+        }
+    }
+
+    //last, expressions can use every kind of symbol but the variables made by a plain @name
+    //a predefined symbol can be any word, so a plain name is checked against 15 bits too
+    for s in statements.iter() {
+        match &s.command {
+            Command::ACommand {
+                symbol: Symbol::Expr(e),
+            } => {
+                let unresolved: Vec<SymbolError> = e
+                    .names()
+                    .into_iter()
+                    .filter(|(n, _)| !table.contains(n) || implicit.contains(n))
+                    .map(|(n, span)| SymbolError::UnresolvedInExpression(n.into(), span))
+                    .collect();
 
-                if let None = table.get(&symbol) {
-                    let mem_str = format!("{:016b}", base_addr);
-                    table.insert(symbol, mem_str);
-                    base_addr += 1;
+                if !unresolved.is_empty() {
+                    errors.extend(unresolved);
+                } else if let Err(e) = table.evaluate(e, s.name_span) {
+                    errors.push(e);
+                }
+            }
+            Command::ACommand {
+                symbol: Symbol::Name(n),
+            } => {
+                if let Some(address) = table.address(n).filter(|a| *a > MAX_A_VALUE) {
+                    errors.push(SymbolError::OutOfRange(address.into(), s.name_span));
                 }
             }
-            Some(_) => continue,
-            None => {}
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(table)
+    } else {
+        Err(errors)
+    }
+}
+
+//adds a label, constant, alias or .var, which can't reuse a name
+//returns whether it was added
+fn define(
+    table: &mut SymbolTable,
+    errors: &mut Vec<SymbolError>,
+    name: &str,
+    address: u16,
+    kind: SymbolKind,
+    s: &Statement,
+) -> bool {
+    match table.get(name) {
+        //predefined names that aren't the platform's were passed in, with -D from the command line
+        Some(e) if e.kind == SymbolKind::Predefined => {
+            if hack_predefined().iter().any(|(n, _)| n == name) {
+                errors.push(SymbolError::RedefinedPredefined(name.into(), s.name_span));
+            } else {
+                errors.push(SymbolError::RedefinedDefine(name.into(), s.name_span));
+            }
+            false
+        }
+
+        Some(e) => {
+            let first = e.span.unwrap_or_default();
+            errors.push(SymbolError::DuplicateSymbol(
+                name.into(),
+                s.name_span,
+                first,
+            ));
+            false
+        }
+
+        None => table.insert(name.into(), address, kind, Some(s.name_span)),
+    }
+}
+
+//finds @LABEL followed by an instruction that reads or writes M, the label's rom address
+//is being used as a ram address which is usually a mistake
+//only a warning, a program can mean to read or write the ram at a label's address
+pub fn labels_used_as_data(statements: &[Statement], table: &SymbolTable) -> Vec<SymbolWarning> {
+    let mut warnings = Vec::new();
+
+    let commands: Vec<&Statement> = statements
+        .iter()
+        .filter(|s| s.command.is_instruction())
+        .collect();
+
+    for pair in commands.windows(2) {
+        let Command::ACommand {
+            symbol: Symbol::Name(name),
+        } = &pair[0].command
+        else {
+            continue;
+        };
+
+        let Some(label) = table
+            .get(name)
+            .filter(|e| e.kind == SymbolKind::Label)
+            .and_then(|e| e.span)
+        else {
+            continue;
+        };
+
+        if let Command::CCommand { dest, comp, .. } = &pair[1].command
+            && (comp.uses_m() || dest.is_some_and(|d| d.has_m()))
+        {
+            warnings.push(SymbolWarning::LabelAsData(
+                name.clone(),
+                pair[0].name_span,
+                label,
+            ));
+        }
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn table_for(src: &str) -> Result<SymbolTable, Vec<SymbolError>> {
+        table_with_options(src, &Options::default())
+    }
+
+    fn table_with_mode(src: &str, mode: SymbolMode) -> Result<SymbolTable, Vec<SymbolError>> {
+        let options = Options {
+            symbol_mode: mode,
+            ..Options::default()
+        };
+        table_with_options(src, &options)
+    }
+
+    fn table_with_options(src: &str, options: &Options) -> Result<SymbolTable, Vec<SymbolError>> {
+        let (statements, errors) = Parser::new(src.as_bytes()).unwrap().parse_all();
+        assert!(errors.is_empty());
+
+        create_table(&statements, options)
+    }
+
+    #[test]
+    fn labels_and_variables() {
+        let src = "// comment\n(LOOP)\n@i\nM=1\n@LOOP\n0;JMP\n";
+        let table = table_for(src).unwrap();
+
+        assert_eq!(table.address("LOOP"), Some(0));
+        assert_eq!(table.address("i"), Some(16));
+    }
+
+    #[test]
+    fn indented_labels_and_comments() {
+        let src = "  // indented comment\n  @i /* note */\n  (LOOP) // top\n  0;JMP\n";
+        let table = table_for(src).unwrap();
+
+        assert_eq!(table.address("LOOP"), Some(1));
+        assert_eq!(table.address("i"), Some(16));
+    }
+
+    #[test]
+    fn variables_out_of_bounds() {
+        let mut src = String::new();
+        for i in 0..=(MAX_VARIABLE_ADDR - 16) {
+            src.push_str(&format!("@v{i}\n"));
+        }
+        src.push_str("@last\n@other\n");
+
+        let errors = table_for(&src).unwrap_err();
+
+        match errors.as_slice() {
+            [
+                SymbolError::OutofBoundsAddress(a, 16, MAX_VARIABLE_ADDR, a_span),
+                SymbolError::OutofBoundsAddress(b, ..),
+            ] => {
+                assert_eq!(a, "last");
+                assert_eq!(*a_span, Span::new(MAX_VARIABLE_ADDR as u32 - 14, 2, 4));
+                assert_eq!(b, "other");
+            }
+            r => panic!("expected out of bounds addresses, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn strict_mode_unresolved() {
+        let src = "(LOOP)\n@LOOP\n@R1\n@LOPP\n0;JMP\n@LOPP\n";
+        let errors = table_with_mode(src, SymbolMode::Strict).unwrap_err();
+
+        let spans: Vec<Span> = errors
+            .iter()
+            .map(|e| match e {
+                SymbolError::UnresolvedSymbol(s, span) if s == "LOPP" => *span,
+                e => panic!("expected unresolved symbol, got {e:?}"),
+            })
+            .collect();
+
+        assert_eq!(spans, vec![Span::new(4, 2, 4), Span::new(6, 2, 4)]);
+
+        //the same source is fine when names become variables
+        assert!(table_for(src).is_ok());
+    }
+
+    #[test]
+    fn duplicate_and_predefined_labels() {
+        let src = "(LOOP)\n@LOOP\n0;JMP\n(LOOP)\n(SCREEN)\n@LOOP\n";
+        let errors = table_for(src).unwrap_err();
+
+        match errors.as_slice() {
+            [
+                SymbolError::DuplicateSymbol(a, second, first),
+                SymbolError::RedefinedPredefined(b, span),
+            ] => {
+                assert_eq!(a, "LOOP");
+                assert_eq!(*second, Span::new(4, 2, 4));
+                assert_eq!(*first, Span::new(1, 2, 4));
+                assert_eq!(b, "SCREEN");
+                assert_eq!(*span, Span::new(5, 2, 6));
+            }
+            r => panic!("expected label errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn label_used_as_data() {
+        let src = "(END)\n@END\n0;JMP\n@END\nD=A\n@END\n(SKIP)\nM=D\n";
+        let (statements, _) = Parser::new(src.as_bytes()).unwrap().parse_all();
+        //a program that does this still assembles
+        let table = create_table(&statements, &Options::default()).unwrap();
+
+        match labels_used_as_data(&statements, &table).as_slice() {
+            [SymbolWarning::LabelAsData(name, used, defined)] => {
+                assert_eq!(name, "END");
+                assert_eq!(*used, Span::new(6, 2, 3));
+                assert_eq!(*defined, Span::new(1, 2, 3));
+            }
+            r => panic!("expected label used as data, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn definition_order_and_kinds() {
+        let src = "@b\n(LOOP)\n@a\n0;JMP\n";
+        let table = table_for(src).unwrap();
+
+        let defined: Vec<(&str, u16, SymbolKind)> = table
+            .iter()
+            .filter(|e| e.kind != SymbolKind::Predefined)
+            .map(|e| (e.name.as_str(), e.address, e.kind))
+            .collect();
+
+        //labels are found in the first pass, so come before the variables
+        assert_eq!(
+            defined,
+            vec![
+                ("LOOP", 1, SymbolKind::Label),
+                ("b", 16, SymbolKind::Variable),
+                ("a", 17, SymbolKind::Variable),
+            ]
+        );
+
+        assert_eq!(table.get("a").unwrap().span, Some(Span::new(3, 2, 1)));
+        assert_eq!(table.get("SCREEN").unwrap().span, None);
+        assert_eq!(table.len(), hack_predefined().len() + 3);
+    }
+
+    #[test]
+    fn custom_predefined_and_base() {
+        let options = Options {
+            predefined: vec![("IO".into(), 8000)],
+            variable_base: 100,
+            ..Options::default()
+        };
+        let table = table_with_options("@IO\n@R0\n@x\n", &options).unwrap();
+
+        assert_eq!(table.address("IO"), Some(8000));
+        //R0 isn't predefined any more, so it's just another variable
+        assert_eq!(table.address("R0"), Some(100));
+        assert_eq!(table.address("x"), Some(101));
+        assert_eq!(table.get("R0").unwrap().kind, SymbolKind::Variable);
+
+        let options = Options {
+            variable_base: 100,
+            variable_limit: 101,
+            ..Options::default()
         };
+        let errors = table_with_options("@x\n@y\n@z\n.var w @ 101\n", &options).unwrap_err();
+
+        //w is placed first, so only x fits
+        match errors.as_slice() {
+            [
+                SymbolError::OutofBoundsAddress(y, 100, 101, _),
+                SymbolError::OutofBoundsAddress(z, ..),
+            ] => assert_eq!((y.as_str(), z.as_str()), ("y", "z")),
+            r => panic!("expected out of bounds addresses, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn constants_and_aliases() {
+        let src = ".equ ROW 32\n.alias i R13\n.alias j ROW\n@i\n(LOOP)\n@x\n";
+        let table = table_for(src).unwrap();
+
+        //definitions don't take rom, so LOOP is still the second instruction
+        assert_eq!(table.address("LOOP"), Some(1));
+        assert_eq!(table.address("ROW"), Some(32));
+        assert_eq!(table.address("i"), Some(13));
+        assert_eq!(table.address("j"), Some(32));
+        //and constants don't take ram either
+        assert_eq!(table.address("x"), Some(16));
+        assert_eq!(table.get("ROW").unwrap().kind, SymbolKind::Constant);
+        assert_eq!(table.get("i").unwrap().kind, SymbolKind::Alias);
+    }
+
+    #[test]
+    fn bad_definitions() {
+        let src = ".equ A 1\n.equ A 2\n.alias k nowhere\n.equ KBD 3\n";
+        let errors = table_for(src).unwrap_err();
+
+        match errors.as_slice() {
+            [
+                SymbolError::DuplicateSymbol(a, _, first),
+                SymbolError::RedefinedPredefined(kbd, _),
+                SymbolError::UndefinedAlias(target, span),
+            ] => {
+                assert_eq!(a, "A");
+                assert_eq!(first.line, 1);
+                assert_eq!(kbd, "KBD");
+                assert_eq!(target, "nowhere");
+                assert_eq!(span.line, 3);
+            }
+            r => panic!("expected definition errors, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn expressions() {
+        let src = ".var table 4\n.equ N 17\n@table+3\n@SCREEN+32*5\n(END)\n@END-1\n@KBD*2\n\
+                   @1/(N-17)\n@count\n@count+1\n@nowhere-1\n";
+        let errors = table_for(src).unwrap_err();
+
+        match errors.as_slice() {
+            [
+                SymbolError::OutOfRange(v, span),
+                SymbolError::DivisionByZero(div),
+                SymbolError::UnresolvedInExpression(count, _),
+                SymbolError::UnresolvedInExpression(nowhere, nowhere_span),
+            ] => {
+                assert_eq!(*v, 49152);
+                assert_eq!(*span, Span::new(7, 2, 5));
+                assert_eq!(div.line, 8);
+                //a plain @count makes a one word variable, there is nothing past it
+                assert_eq!(count, "count");
+                assert_eq!(nowhere, "nowhere");
+                assert_eq!(*nowhere_span, Span::new(11, 2, 7));
+            }
+            r => panic!("expected expression errors, got {r:?}"),
+        }
+
+        let table = table_for(".var table 4\n@table+3\n@SCREEN+32*5\n(END)\n@END-1\n@i\n").unwrap();
+        assert_eq!(table.address("table"), Some(16));
+        assert_eq!(table.address("i"), Some(20));
+    }
+
+    #[test]
+    fn aliases_to_variables() {
+        let table = table_for(".alias p count\n@p\n@count\n").unwrap();
+
+        //p is only an alias, so count is the one variable
+        assert_eq!(table.address("count"), Some(16));
+        assert_eq!(table.address("p"), Some(16));
+        assert_eq!(table.get("p").unwrap().kind, SymbolKind::Alias);
     }
 
-    Ok(table)
-    //second pass to add variables
+    #[test]
+    fn sized_and_placed_variables() {
+        let src = "@i\n.var buf 3\n.var flag @ 20\n.var big 2000\n.alias first buf\n@j\n";
+        let table = table_for(src).unwrap();
+
+        //.vars come before @name variables, and the others go around the placed ones
+        assert_eq!(table.address("buf"), Some(16));
+        assert_eq!(table.address("flag"), Some(20));
+        assert_eq!(table.address("big"), Some(21));
+        assert_eq!(table.address("first"), Some(16));
+        assert_eq!(table.address("i"), Some(2021));
+        assert_eq!(table.address("j"), Some(2022));
+        assert_eq!(table.get("buf").unwrap().kind, SymbolKind::Variable);
+    }
+
+    #[test]
+    fn variable_overlaps() {
+        let src = ".var a 10 @ 100\n.var b @ 105\n.var c 2 @ 16383\n.var d 16300\n.var a\n";
+        let errors = table_for(src).unwrap_err();
+
+        match errors.as_slice() {
+            [
+                SymbolError::VariableOverlap(b, span, other),
+                SymbolError::ReservedMemory(c, MAX_VARIABLE_ADDR, _),
+                SymbolError::OutofBoundsAddress(d, ..),
+                SymbolError::DuplicateSymbol(a, ..),
+            ] => {
+                assert_eq!(b, "b");
+                assert_eq!(*span, Span::new(2, 6, 1));
+                assert_eq!(*other, Span::new(1, 6, 1));
+                assert_eq!(c, "c");
+                assert_eq!(d, "d");
+                assert_eq!(a, "a");
+            }
+            r => panic!("expected overlapping variables, got {r:?}"),
+        }
+    }
 }